@@ -0,0 +1,126 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use libc::{c_int, SIGINT, SIGKILL, SIGTERM};
+use signal_hook::iterator::Signals;
+
+/// How long children get to shut down after being asked to, before they are killed.
+const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Tracks the `make` children that are currently running, so that they can
+/// all be stopped when a target fails or when `mak` receives a signal.
+///
+/// Each child is spawned in its own process group (with the child's PID as the
+/// group ID), so that signals also reach any processes started by its recipe.
+#[derive(Default)]
+pub(crate) struct Cancellation {
+    process_groups: Mutex<HashSet<i32>>,
+    cancelled: AtomicBool,
+    /// The signal that caused cancellation, or 0 if it was triggered by a failure.
+    signal: AtomicI32,
+}
+
+impl Cancellation {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// The signal that caused cancellation, if any.
+    pub(crate) fn signal(&self) -> Option<c_int> {
+        match self.signal.load(Ordering::SeqCst) {
+            0 => None,
+            signal => Some(signal),
+        }
+    }
+
+    /// Registers a newly spawned child. If cancellation is already in
+    /// progress, the child is asked to stop right away.
+    pub(crate) fn register(&self, process_group: u32) {
+        let process_group = process_group as i32;
+        let mut process_groups = self.lock_process_groups();
+        process_groups.insert(process_group);
+        if self.is_cancelled() {
+            signal_process_group(process_group, SIGTERM);
+        }
+    }
+
+    pub(crate) fn unregister(&self, process_group: u32) {
+        self.lock_process_groups().remove(&(process_group as i32));
+    }
+
+    /// Asks all running children to stop, and kills any that are still
+    /// running after the grace period. Only the first call (since the last
+    /// `reset()`) has any effect.
+    pub(crate) fn cancel(self: &Arc<Self>, signal: Option<c_int>) {
+        if self.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        self.signal.store(signal.unwrap_or(0), Ordering::SeqCst);
+        self.signal_all(signal.unwrap_or(SIGTERM));
+
+        // Only children that were running at the time of cancellation are
+        // killed, in case another build has started in the meantime (`--watch`).
+        let process_groups = self.lock_process_groups().clone();
+        let cancellation = self.clone();
+        thread::spawn(move || {
+            thread::sleep(GRACE_PERIOD);
+            for process_group in cancellation
+                .lock_process_groups()
+                .intersection(&process_groups)
+            {
+                signal_process_group(*process_group, SIGKILL);
+            }
+        });
+    }
+
+    /// Allows another build to run after a build was cancelled because of a
+    /// failure. Cancellation due to a signal is permanent.
+    pub(crate) fn reset(&self) {
+        if self.signal().is_none() {
+            self.cancelled.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Cancels the build on SIGINT or SIGTERM. A second signal kills all
+    /// children immediately instead of waiting for the grace period.
+    pub(crate) fn forward_signals(self: &Arc<Self>) {
+        let mut signals =
+            Signals::new([SIGINT, SIGTERM]).expect("Could not register signal handlers.");
+        let cancellation = self.clone();
+        thread::spawn(move || {
+            for signal in signals.forever() {
+                if cancellation.is_cancelled() {
+                    cancellation.signal_all(SIGKILL);
+                } else {
+                    cancellation.cancel(Some(signal));
+                }
+            }
+        });
+    }
+
+    fn signal_all(&self, signal: c_int) {
+        for process_group in self.lock_process_groups().iter() {
+            signal_process_group(*process_group, signal);
+        }
+    }
+
+    fn lock_process_groups(&self) -> std::sync::MutexGuard<'_, HashSet<i32>> {
+        self.process_groups
+            .lock()
+            .expect("Could not access running processes.")
+    }
+}
+
+fn signal_process_group(process_group: i32, signal: c_int) {
+    // Ignore failures, since the group may have exited in the meantime.
+    unsafe {
+        libc::killpg(process_group, signal);
+    }
+}
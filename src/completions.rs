@@ -0,0 +1,270 @@
+use clap::Command;
+use clap_complete::{generator::generate, Shell};
+
+/// Completes targets by calling `mak --print-completion-targets`, passing on
+/// the Makefile from `-f path`, `-fpath`, `--file path`, `--file=path`, or `--makefile …`.
+///
+/// Each script is added to (or spliced into) the script that `clap` generates
+/// for completing options, and `{value_options}` is replaced by the options that take a value.
+const BASH_TARGET_COMPLETIONS: &str = r#"
+_mak_targets() {
+    local cur="$1" file="" i word target
+    for (( i = 1; i < COMP_CWORD; i++ )); do
+        word="${COMP_WORDS[i]}"
+        case "${word}" in
+            -f|--file|--makefile)
+                # `--file=path` is split into three words.
+                if [[ "${COMP_WORDS[i+1]}" == "=" ]]; then
+                    file="${COMP_WORDS[i+2]}"
+                else
+                    file="${COMP_WORDS[i+1]}"
+                fi
+                ;;
+            --file=*|--makefile=*)
+                file="${word#*=}"
+                ;;
+            -f?*)
+                file="${word#-f}"
+                file="${file#=}"
+                ;;
+        esac
+    done
+    while IFS=$'\t' read -r target _; do
+        if [[ "${target}" == "${cur}"* ]]; then
+            COMPREPLY+=("${target}")
+        fi
+    done < <(mak ${file:+--file "${file}"} --print-completion-targets 2>/dev/null)
+}
+
+_mak_with_targets() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local prev="${COMP_WORDS[COMP_CWORD-1]}"
+    if [[ "${prev}" == "=" ]]; then
+        prev="${COMP_WORDS[COMP_CWORD-2]}"
+    fi
+    COMPREPLY=()
+    if [[ "${cur}" == -* || "${cur}" == "=" ]]; then
+        _mak "$@"
+        return
+    fi
+    case "${prev}" in
+        {value_options})
+            _mak "$@"
+            ;;
+        *)
+            _mak_targets "${cur}"
+            ;;
+    esac
+}
+
+complete -F _mak_with_targets -o nosort -o bashdefault -o default mak
+"#;
+
+const ZSH_TARGET_COMPLETIONS: &str = r#"
+(( $+functions[_mak_targets] )) ||
+_mak_targets() {
+    local file=${opt_args[--file]:-${opt_args[-f]}}
+    local -a file_args targets
+    if [[ -n $file ]]; then
+        file_args=(--file ${(Q)file})
+    fi
+    targets=(${(f)"$(mak $file_args --print-completion-targets 2>/dev/null)"})
+    # `_describe` separates targets from their descriptions with `:`.
+    targets=(${targets//:/\\:})
+    targets=(${targets/$'\t'/:})
+    _describe -t targets 'Makefile target' targets
+}
+"#;
+
+const FISH_TARGET_COMPLETIONS: &str = r#"
+# Complete targets for `fish` similarly to https://github.com/fish-shell/fish-shell/blob/3ce67ecbd2348fbe13e86a00bea6ce998710729a/share/completions/make.fish
+function __fish_complete_mak_targets
+    set -l file (string replace -rf '^mak(?:.* )? ?(?:-f=?|--file[= ]|--makefile[= ]) *([^ ]+) .*$' '$1' -- $argv)
+    if test -n "$file"
+        mak --file "$file" --print-completion-targets
+    else
+        mak --print-completion-targets
+    end
+end
+complete -c mak -n 'commandline -ct | string match -q "*=*"' -a "(__fish_complete_mak_targets (commandline -p))" -d Target
+complete -f -c mak -n 'commandline -ct | not string match -q "*=*"' -a "(__fish_complete_mak_targets (commandline -p))" -d Target
+"#;
+
+const ELVISH_TARGET_COMPLETIONS: &str = r#"
+use re;
+
+var mak-option-completer = $edit:completion:arg-completer[mak]
+set edit:completion:arg-completer[mak] = {|@words|
+    if (or (str:has-prefix $words[-1] '-') (has-value [{value_options}] $words[-2])) {
+        $mak-option-completer $@words
+        return
+    }
+    var file = ''
+    var previous = ''
+    for word $words[1..-1] {
+        if (has-value ['-f' '--file' '--makefile'] $previous) {
+            set file = $word
+        } elif (re:match '^--(file|makefile)=' $word) {
+            set file = (re:replace '^--(file|makefile)=' '' $word)
+        } elif (re:match '^-f.' $word) {
+            set file = (re:replace '^-f=?' '' $word)
+        }
+        set previous = $word
+    }
+    var file-args = []
+    if (not-eq $file '') {
+        set file-args = ['--file' $file]
+    }
+    for line [(mak $@file-args --print-completion-targets)] {
+        var parts = [(str:split "\t" $line)]
+        if (== (count $parts) 2) {
+            edit:complex-candidate $parts[0] &display=$parts[0]'  '$parts[1]
+        } else {
+            put $line
+        }
+    }
+}
+"#;
+
+/// Inserted at the start of the `Register-ArgumentCompleter` script block.
+const POWERSHELL_TARGET_COMPLETIONS: &str = r#"
+    $makTargets = @()
+    if (-not $wordToComplete.StartsWith('-')) {
+        $makFileArgs = @()
+        $elements = $commandAst.CommandElements
+        for ($i = 1; $i -lt $elements.Count; $i++) {
+            $text = $elements[$i].Extent.Text
+            if ($text -in '-f', '--file', '--makefile') {
+                if ($i + 1 -lt $elements.Count) {
+                    $next = $elements[$i + 1]
+                    $makFileArgs = @('--file', $(if ($next -is [StringConstantExpressionAst]) { $next.Value } else { $next.Extent.Text }))
+                }
+            } elseif ($text -match '^(?:--file=|--makefile=|-f=?)(.+)$') {
+                $makFileArgs = @('--file', $Matches[1])
+            }
+        }
+        $makTargets = @(mak @makFileArgs --print-completion-targets) | ForEach-Object {
+            $target, $description = $_ -split "`t", 2
+            [CompletionResult]::new($target, $target, [CompletionResultType]::ParameterValue, $(if ($description) { $description } else { $target }))
+        }
+    }
+"#;
+
+/// Prints the completion script for `shell`, including completions for targets.
+pub(crate) fn print_completions(command: &mut Command, shell: Shell) {
+    print!("{}", completion_script(command, shell));
+}
+
+fn completion_script(command: &mut Command, shell: Shell) -> String {
+    let mut buffer = vec![];
+    generate(shell, command, "mak", &mut buffer);
+    let script = String::from_utf8(buffer).expect("Could not generate completions.");
+    let value_options = value_options(command);
+    match shell {
+        Shell::Bash => {
+            script + &BASH_TARGET_COMPLETIONS.replace("{value_options}", &value_options.join("|"))
+        }
+        Shell::Zsh => {
+            // Targets are completed as the positional arguments, and the
+            // function has to be defined before `_mak` is called at the end.
+            let script: String = script
+                .split_inclusive('\n')
+                .map(|line| match line.strip_suffix(":' \\\n") {
+                    Some(spec) if spec.starts_with("'*::targets -- ") => {
+                        format!("{}:_mak_targets' \\\n", spec)
+                    }
+                    _ => line.to_owned(),
+                })
+                .collect();
+            match script.split_once('\n') {
+                Some((compdef_line, rest)) => {
+                    format!("{}\n{}{}", compdef_line, ZSH_TARGET_COMPLETIONS, rest)
+                }
+                None => script,
+            }
+        }
+        Shell::Fish => script + FISH_TARGET_COMPLETIONS,
+        Shell::Elvish => {
+            let value_options: Vec<String> = value_options
+                .iter()
+                .map(|option| format!("'{}'", option))
+                .collect();
+            script + &ELVISH_TARGET_COMPLETIONS.replace("{value_options}", &value_options.join(" "))
+        }
+        Shell::PowerShell => {
+            // Targets are added to the candidates for the options.
+            let param_line = "    param($wordToComplete, $commandAst, $cursorPosition)\n";
+            script
+                .replacen(
+                    param_line,
+                    &format!("{}{}", param_line, POWERSHELL_TARGET_COMPLETIONS),
+                    1,
+                )
+                .replacen(
+                    "        'mak' {\n",
+                    "        'mak' {\n            $makTargets\n",
+                    1,
+                )
+        }
+        _ => script,
+    }
+}
+
+/// All spellings of options that take a value (after which a target can't be completed).
+fn value_options(command: &Command) -> Vec<String> {
+    let mut value_options = vec![];
+    for arg in command.get_arguments() {
+        if arg.is_positional() || !arg.get_action().takes_values() {
+            continue;
+        }
+        for short in arg.get_short_and_visible_aliases().into_iter().flatten() {
+            value_options.push(format!("-{}", short));
+        }
+        for long in arg.get_long_and_visible_aliases().into_iter().flatten() {
+            value_options.push(format!("--{}", long));
+        }
+        for alias in arg.get_all_aliases().into_iter().flatten() {
+            value_options.push(format!("--{}", alias));
+        }
+    }
+    value_options.sort();
+    value_options.dedup();
+    value_options
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+    use clap_complete::Shell;
+
+    use crate::options::MakArgs;
+
+    use super::{completion_script, value_options};
+
+    #[test]
+    fn test_completion_scripts() {
+        let mut command = MakArgs::command();
+        let value_options = value_options(&command);
+        assert!(value_options.contains(&"-f".to_owned()));
+        assert!(value_options.contains(&"--makefile".to_owned()));
+        assert!(value_options.contains(&"--jobs".to_owned()));
+        assert!(!value_options.contains(&"--keep-going".to_owned()));
+
+        for shell in [
+            Shell::Bash,
+            Shell::Zsh,
+            Shell::Fish,
+            Shell::Elvish,
+            Shell::PowerShell,
+        ] {
+            let script = completion_script(&mut command, shell);
+            assert!(!script.contains("{value_options}"));
+        }
+        // Splicing relies on the exact output of `clap_complete`.
+        let zsh_script = completion_script(&mut command, Shell::Zsh);
+        assert!(zsh_script.starts_with("#compdef mak\n"));
+        assert!(zsh_script.contains(":_mak_targets' \\\n"));
+        let powershell_script = completion_script(&mut command, Shell::PowerShell);
+        assert!(powershell_script.contains("        'mak' {\n            $makTargets\n"));
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use console::{measure_text_width, pad_str, style, Alignment};
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::parse::{TargetGraph, TargetName};
+
+/// Target names longer than this don't push the other descriptions further right.
+const MAX_NAME_COLUMN_WIDTH: usize = 30;
+
+/// Documentation for targets, read from the Makefile source (since the `make`
+/// database doesn't include comments). Supports the common conventions:
+///
+/// ```makefile
+/// ##@ Section header
+///
+/// build: deps ## Inline description
+///
+/// # Description on the line(s) before the rule.
+/// .PHONY: test
+/// test:
+/// ```
+#[derive(Debug, Default)]
+pub(crate) struct TargetDescriptions {
+    /// In the order the targets are first defined in the source.
+    targets: IndexMap<TargetName, TargetDoc>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct TargetDoc {
+    pub(crate) section: Option<String>,
+    pub(crate) description: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ListedTarget<'a> {
+    name: &'a TargetName,
+    #[serde(flatten)]
+    doc: &'a TargetDoc,
+}
+
+/// The Makefiles that `make` read, from the `MAKEFILE_LIST` variable in its
+/// database. They are relative to the directory that `make` ran in.
+pub(crate) fn makefile_list(database: &str, make_directory: &Path) -> Vec<PathBuf> {
+    database
+        .lines()
+        .find_map(|line| line.strip_prefix("MAKEFILE_LIST := "))
+        .map(|value| {
+            value
+                .split_whitespace()
+                .map(|path| make_directory.join(path))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl TargetDescriptions {
+    /// Files that can't be read (e.g. ones that `make` generated) are skipped.
+    pub(crate) fn read(makefile_paths: &[PathBuf]) -> Self {
+        let mut target_descriptions = Self::default();
+        for makefile_path in makefile_paths {
+            if let Ok(source) = fs::read_to_string(makefile_path) {
+                target_descriptions.add_source(&source);
+            }
+        }
+        target_descriptions
+    }
+
+    pub(crate) fn add_source(&mut self, source: &str) {
+        let mut section: Option<String> = None;
+        let mut preceding_comment_lines: Vec<String> = vec![];
+        let mut lines = source.lines();
+        while let Some(line) = lines.next() {
+            if line.starts_with('\t') {
+                preceding_comment_lines.clear();
+                continue;
+            }
+            // Join continuation lines, so that they aren't mistaken for rules.
+            let mut line = line.to_owned();
+            while line.ends_with('\\') {
+                line.pop();
+                match lines.next() {
+                    Some(next_line) => line.push_str(next_line),
+                    None => break,
+                }
+            }
+
+            let trimmed = line.trim();
+            if let Some(section_name) = trimmed.strip_prefix("##@") {
+                section = Some(section_name.trim().to_owned()).filter(|s| !s.is_empty());
+                preceding_comment_lines.clear();
+                continue;
+            }
+            if let Some(comment) = trimmed.strip_prefix('#') {
+                let comment = comment.trim_start_matches('#').trim();
+                if !comment.is_empty() {
+                    preceding_comment_lines.push(comment.to_owned());
+                }
+                continue;
+            }
+
+            let (rule, inline_description) = match line.split_once("##") {
+                Some((rule, description)) => (rule, Some(description.trim())),
+                None => (line.as_str(), None),
+            };
+            let Some(target_names) = rule_targets(rule) else {
+                preceding_comment_lines.clear();
+                continue;
+            };
+            // Keep the comment for the rule after declarations like `.PHONY: test`.
+            if target_names.iter().all(|t| t.0.starts_with('.')) {
+                continue;
+            }
+            let description = inline_description
+                .filter(|description| !description.is_empty())
+                .map(|description| description.to_owned())
+                .or_else(|| {
+                    (!preceding_comment_lines.is_empty()).then(|| preceding_comment_lines.join(" "))
+                });
+            preceding_comment_lines.clear();
+            for target_name in target_names {
+                let target_doc = self
+                    .targets
+                    .entry(target_name)
+                    .or_insert_with(|| TargetDoc {
+                        section: section.clone(),
+                        description: None,
+                    });
+                if target_doc.description.is_none() {
+                    target_doc.description = description.clone();
+                }
+            }
+        }
+    }
+
+    pub(crate) fn description(&self, target_name: &TargetName) -> Option<&str> {
+        self.targets.get(target_name)?.description.as_deref()
+    }
+
+    /// Targets that can be built, in source order, followed by any that
+    /// couldn't be found in the source (e.g. because their names use variables).
+    fn listed_targets<'a>(&'a self, target_graph: &'a TargetGraph) -> Vec<ListedTarget<'a>> {
+        static UNDOCUMENTED: TargetDoc = TargetDoc {
+            section: None,
+            description: None,
+        };
+        let is_listed = |target_name: &TargetName| {
+            target_graph
+                .metadata
+                .get(target_name)
+                .is_some_and(|metadata| !metadata.not_a_target)
+        };
+        let documented = self
+            .targets
+            .iter()
+            .filter(|(target_name, _)| is_listed(target_name))
+            .map(|(name, doc)| ListedTarget { name, doc });
+        let undocumented = target_graph
+            .edges
+            .keys()
+            .filter(|target_name| {
+                is_listed(target_name) && !self.targets.contains_key(*target_name)
+            })
+            .map(|name| ListedTarget {
+                name,
+                doc: &UNDOCUMENTED,
+            });
+        documented.chain(undocumented).collect()
+    }
+
+    /// Prints the targets with their descriptions, grouped by section.
+    pub(crate) fn print_list(&self, target_graph: &TargetGraph) {
+        let listed_targets = self.listed_targets(target_graph);
+        let name_column_width = listed_targets
+            .iter()
+            .map(|listed_target| measure_text_width(&listed_target.name.0))
+            .filter(|width| *width <= MAX_NAME_COLUMN_WIDTH)
+            .max()
+            .unwrap_or_default();
+
+        let mut sections = IndexMap::<Option<&str>, Vec<&ListedTarget>>::new();
+        // Targets without a section come first.
+        sections.insert(None, vec![]);
+        for listed_target in &listed_targets {
+            sections
+                .entry(listed_target.doc.section.as_deref())
+                .or_default()
+                .push(listed_target);
+        }
+        let mut is_first_section = true;
+        for (section, listed_targets) in sections {
+            if listed_targets.is_empty() {
+                continue;
+            }
+            if let Some(section) = section {
+                if !is_first_section {
+                    println!();
+                }
+                println!("{}", style(section).bold());
+            }
+            is_first_section = false;
+            for listed_target in listed_targets {
+                let name = &listed_target.name.0;
+                match &listed_target.doc.description {
+                    Some(description) => println!(
+                        "  {}  {}",
+                        style(pad_str(name, name_column_width, Alignment::Left, None)).cyan(),
+                        description
+                    ),
+                    None => println!("  {}", style(name).cyan()),
+                }
+            }
+        }
+    }
+
+    /// Like `print_list()`, as a JSON array.
+    pub(crate) fn print_list_json(&self, target_graph: &TargetGraph) {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&self.listed_targets(target_graph))
+                .expect("Could not print target list")
+        );
+    }
+}
+
+/// The targets defined by a line of Makefile source, if it is a rule (rather
+/// than e.g. a variable assignment or a directive). Pattern rules and targets
+/// with variables in their names are left out.
+fn rule_targets(line: &str) -> Option<Vec<TargetName>> {
+    // A single `#` starts a normal comment.
+    let line = line.split('#').next().unwrap_or_default();
+    let (targets, rest) = line.split_once(':')?;
+    // `:=`, `::=`, and `:::=` are assignments (`::` on its own is a double-colon
+    // rule), as are target-specific variables like `build: CC = clang`. An
+    // inline recipe after `;` may contain `=` though.
+    let prerequisites = rest.split(';').next().unwrap_or_default();
+    if prerequisites.contains('=') || targets.contains('=') {
+        return None;
+    }
+    let first_word = targets.split_whitespace().next()?;
+    if matches!(
+        first_word,
+        "define" | "ifeq" | "ifneq" | "ifdef" | "ifndef" | "export" | "override" | "vpath"
+    ) {
+        return None;
+    }
+    Some(
+        targets
+            .split_whitespace()
+            .filter(|target| !target.contains('%') && !target.contains('$'))
+            .map(|target| TargetName(target.to_owned()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::parse::TargetName;
+
+    use super::{makefile_list, TargetDescriptions, TargetDoc};
+
+    #[test]
+    fn test_target_descriptions() {
+        let mut target_descriptions = TargetDescriptions::default();
+        target_descriptions.add_source(
+            "VERSION := 1.2 ## not a target
+# C compiler for the tests.
+test: CC = clang
+lint: FLAGS += --strict
+# Builds everything.
+# (Slowly.)
+.PHONY: build
+build: lib.js ## Build the library
+
+##@ Testing
+
+# Runs the tests.
+test: build
+\t# not a description
+\t./test.sh
+
+lint: \\
+  build
+dev-%: ## pattern rules are skipped
+lib.js: src/*.js
+
+# unrelated
+
+clean:
+dist: ; tar -cf dist.tar OUT=out
+",
+        );
+        let doc = |target: &str| {
+            target_descriptions
+                .targets
+                .get(&TargetName(target.to_owned()))
+                .cloned()
+        };
+        let documented = |section: Option<&str>, description: Option<&str>| {
+            Some(TargetDoc {
+                section: section.map(|s| s.to_owned()),
+                description: description.map(|d| d.to_owned()),
+            })
+        };
+        assert_eq!(doc("build"), documented(None, Some("Build the library")));
+        assert_eq!(
+            doc("test"),
+            documented(Some("Testing"), Some("Runs the tests."))
+        );
+        assert_eq!(doc("lint"), documented(Some("Testing"), None));
+        assert_eq!(doc("lib.js"), documented(Some("Testing"), None));
+        assert_eq!(doc("clean"), documented(Some("Testing"), None));
+        assert_eq!(doc("dist"), documented(Some("Testing"), None));
+        assert_eq!(doc("VERSION"), None);
+        assert_eq!(doc("dev-%"), None);
+        assert_eq!(doc("  build"), None);
+        assert_eq!(
+            target_descriptions
+                .targets
+                .keys()
+                .map(|t| t.0.as_str())
+                .collect::<Vec<_>>(),
+            vec!["build", "test", "lint", "lib.js", "clean", "dist"]
+        );
+
+        assert_eq!(
+            makefile_list(
+                "# makefile\nMAKEFILE_LIST := Makefile other.mk\n",
+                Path::new("sub")
+            ),
+            vec![PathBuf::from("sub/Makefile"), PathBuf::from("sub/other.mk")]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{
+    parse::TargetName,
+    render::{Renderer, TargetRenderer},
+    OutputLine,
+};
+
+/// A build event for `--output-format json`, printed as a single line of JSON.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum BuildEvent<'a> {
+    /// The target has been scheduled, and is waiting for its dependencies.
+    TargetQueued {
+        target: &'a TargetName,
+        depth: usize,
+    },
+    /// `make` has been invoked for the target.
+    TargetStarted {
+        target: &'a TargetName,
+        depth: usize,
+    },
+    OutputLine {
+        target: &'a TargetName,
+        stream: &'static str,
+        line: &'a str,
+    },
+    TargetSucceeded {
+        target: &'a TargetName,
+        depth: usize,
+        duration_ms: u64,
+        /// Whether the target was already up to date, in which case `make` was never invoked.
+        up_to_date: bool,
+    },
+    TargetFailed {
+        target: &'a TargetName,
+        depth: usize,
+        duration_ms: u64,
+        log_path: Option<&'a Path>,
+    },
+    /// Not attempted, because a dependency failed.
+    TargetSkipped {
+        target: &'a TargetName,
+        depth: usize,
+    },
+    TargetCancelled {
+        target: &'a TargetName,
+        depth: usize,
+    },
+    BuildFinished {
+        success: bool,
+        duration_ms: u64,
+        num_targets: usize,
+        num_up_to_date: usize,
+        num_skipped: usize,
+        num_cancelled: usize,
+        failed_targets: Vec<&'a TargetName>,
+    },
+}
+
+#[derive(Serialize)]
+struct EventLine<'a> {
+    /// Milliseconds since the Unix epoch.
+    timestamp_ms: u64,
+    /// Milliseconds since the start of the build.
+    elapsed_ms: u64,
+    #[serde(flatten)]
+    event: BuildEvent<'a>,
+}
+
+pub(crate) struct EventLog {
+    start_time: Instant,
+}
+
+impl EventLog {
+    pub(crate) fn new(start_time: Instant) -> Self {
+        Self { start_time }
+    }
+
+    pub(crate) fn emit(&self, event: BuildEvent) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time is before the epoch.");
+        let event_line = EventLine {
+            timestamp_ms: millis(timestamp),
+            elapsed_ms: millis(Instant::now() - self.start_time),
+            event,
+        };
+        println!(
+            "{}",
+            serde_json::to_string(&event_line).expect("Could not serialize build event.")
+        );
+    }
+}
+
+pub(crate) fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+/// Emits target events instead of drawing progress.
+pub(crate) struct JsonRenderer {
+    pub(crate) events: Arc<EventLog>,
+}
+
+impl Renderer for JsonRenderer {
+    fn add_target(&self, target_name: &TargetName, depth: usize) -> Box<dyn TargetRenderer> {
+        self.events.emit(BuildEvent::TargetQueued {
+            target: target_name,
+            depth,
+        });
+        Box::new(JsonTargetRenderer {
+            events: self.events.clone(),
+            target_name: target_name.clone(),
+            depth,
+            start_time: Mutex::new(None),
+        })
+    }
+}
+
+struct JsonTargetRenderer {
+    events: Arc<EventLog>,
+    target_name: TargetName,
+    depth: usize,
+    start_time: Mutex<Option<Instant>>,
+}
+
+impl JsonTargetRenderer {
+    fn duration_ms(&self) -> u64 {
+        self.start_time
+            .lock()
+            .expect("Could not access target start time.")
+            .map(|start_time| millis(Instant::now() - start_time))
+            .unwrap_or(0)
+    }
+}
+
+impl TargetRenderer for JsonTargetRenderer {
+    fn waiting_for_job(&self) {}
+
+    fn started(&self) {
+        *self
+            .start_time
+            .lock()
+            .expect("Could not access target start time.") = Some(Instant::now());
+        self.events.emit(BuildEvent::TargetStarted {
+            target: &self.target_name,
+            depth: self.depth,
+        });
+    }
+
+    fn output_line(&self, output_line: &OutputLine) {
+        let (stream, line) = match output_line {
+            OutputLine::Stdout(line) => ("stdout", line),
+            OutputLine::Stderr(line) => ("stderr", line),
+        };
+        self.events.emit(BuildEvent::OutputLine {
+            target: &self.target_name,
+            stream,
+            line,
+        });
+    }
+
+    fn finish_succeeded(&self, _dry_run_commands: Option<Vec<String>>) {
+        self.events.emit(BuildEvent::TargetSucceeded {
+            target: &self.target_name,
+            depth: self.depth,
+            duration_ms: self.duration_ms(),
+            up_to_date: false,
+        });
+    }
+
+    fn finish_up_to_date(&self) {
+        self.events.emit(BuildEvent::TargetSucceeded {
+            target: &self.target_name,
+            depth: self.depth,
+            duration_ms: 0,
+            up_to_date: true,
+        });
+    }
+
+    fn finish_failed(&self, log_path: Option<&Path>) {
+        self.events.emit(BuildEvent::TargetFailed {
+            target: &self.target_name,
+            depth: self.depth,
+            duration_ms: self.duration_ms(),
+            log_path,
+        });
+    }
+
+    fn finish_skipped(&self) {
+        self.events.emit(BuildEvent::TargetSkipped {
+            target: &self.target_name,
+            depth: self.depth,
+        });
+    }
+
+    fn finish_cancelled(&self) {
+        self.events.emit(BuildEvent::TargetCancelled {
+            target: &self.target_name,
+            depth: self.depth,
+        });
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use indexmap::IndexMap;
+
+use crate::parse::{TargetGraph, TargetMetadata, TargetName};
+
+impl TargetGraph {
+    /// Returns the part of the graph that is reachable from `root_target_names`
+    /// (or the whole graph, if there are none) within `max_depth` dependency
+    /// edges. If `hide_phony` is set, phony targets and files that are not
+    /// targets are left out, and their dependencies are connected directly to
+    /// the targets that depend on them.
+    pub(crate) fn subgraph(
+        &self,
+        root_target_names: &[TargetName],
+        max_depth: Option<usize>,
+        hide_phony: bool,
+    ) -> TargetGraph {
+        let roots: Vec<&TargetName> = if root_target_names.is_empty() {
+            self.edges.keys().collect()
+        } else {
+            root_target_names.iter().collect()
+        };
+
+        // Breadth-first, so that each target is reached at its minimum depth.
+        let mut depths = HashMap::<TargetName, usize>::new();
+        let mut queue: VecDeque<(TargetName, usize)> =
+            roots.into_iter().map(|t| (t.clone(), 0)).collect();
+        while let Some((target_name, depth)) = queue.pop_front() {
+            if depths.contains_key(&target_name) || !self.edges.contains_key(&target_name) {
+                continue;
+            }
+            depths.insert(target_name.clone(), depth);
+            if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                continue;
+            }
+            for dependency in self.all_dependencies(&target_name).unwrap_or_default() {
+                queue.push_back((dependency, depth + 1));
+            }
+        }
+
+        let is_hidden = |target_name: &TargetName| {
+            hide_phony
+                && self
+                    .metadata
+                    .get(target_name)
+                    .is_some_and(|metadata| metadata.phony || metadata.not_a_target)
+        };
+        let is_shown =
+            |target_name: &TargetName| depths.contains_key(target_name) && !is_hidden(target_name);
+        // Follows dependencies through hidden targets, until shown ones are reached.
+        let shown_dependencies = |dependencies: &[TargetName]| -> Vec<TargetName> {
+            let mut shown = vec![];
+            let mut visited = HashSet::<TargetName>::new();
+            let mut stack: Vec<TargetName> = dependencies.iter().rev().cloned().collect();
+            while let Some(dependency) = stack.pop() {
+                if !depths.contains_key(&dependency) || !visited.insert(dependency.clone()) {
+                    continue;
+                }
+                if is_shown(&dependency) {
+                    shown.push(dependency);
+                    continue;
+                }
+                let mut next = self.all_dependencies(&dependency).unwrap_or_default();
+                next.reverse();
+                stack.extend(next);
+            }
+            shown
+        };
+
+        let mut subgraph = TargetGraph {
+            edges: IndexMap::new(),
+            order_only_edges: IndexMap::new(),
+            metadata: IndexMap::new(),
+            pattern_rules: self.pattern_rules.clone(),
+            default_goal: self.default_goal.clone().filter(is_shown),
+        };
+        for (target_name, dependencies) in &self.edges {
+            if !is_shown(target_name) {
+                continue;
+            }
+            subgraph
+                .edges
+                .insert(target_name.clone(), shown_dependencies(dependencies));
+            if let Some(order_only_dependencies) = self.order_only_edges.get(target_name) {
+                subgraph.order_only_edges.insert(
+                    target_name.clone(),
+                    shown_dependencies(order_only_dependencies),
+                );
+            }
+            if let Some(metadata) = self.metadata.get(target_name) {
+                subgraph
+                    .metadata
+                    .insert(target_name.clone(), metadata.clone());
+            }
+        }
+        subgraph
+    }
+
+    /// Edges point from each target to its dependencies, with order-only dependencies dashed.
+    pub(crate) fn to_dot(&self) -> String {
+        let mut lines = vec!["digraph mak {".to_owned()];
+        for target_name in self.edges.keys() {
+            let style = match self.metadata.get(target_name) {
+                Some(TargetMetadata { phony: true, .. }) => ", style=dashed",
+                Some(TargetMetadata {
+                    not_a_target: true, ..
+                }) => ", shape=note",
+                _ => "",
+            };
+            lines.push(format!(
+                "  {} [label={}{}];",
+                dot_id(target_name),
+                dot_id(target_name),
+                style
+            ));
+        }
+        for (target_name, dependencies) in &self.edges {
+            for dependency in dependencies {
+                lines.push(format!(
+                    "  {} -> {};",
+                    dot_id(target_name),
+                    dot_id(dependency)
+                ));
+            }
+            for dependency in self.order_only_edges.get(target_name).into_iter().flatten() {
+                lines.push(format!(
+                    "  {} -> {} [style=dashed];",
+                    dot_id(target_name),
+                    dot_id(dependency)
+                ));
+            }
+        }
+        lines.push("}".to_owned());
+        lines.join("\n")
+    }
+
+    /// A flowchart with the same conventions as `to_dot()`, with phony targets shown as rounded nodes.
+    pub(crate) fn to_mermaid(&self) -> String {
+        let ids: HashMap<&TargetName, String> = self
+            .edges
+            .keys()
+            .enumerate()
+            .map(|(i, target_name)| (target_name, format!("n{}", i)))
+            .collect();
+        let mut lines = vec!["flowchart TD".to_owned()];
+        for target_name in self.edges.keys() {
+            let label = mermaid_label(target_name);
+            let node = match self.metadata.get(target_name) {
+                Some(TargetMetadata { phony: true, .. }) => format!("([{}])", label),
+                Some(TargetMetadata {
+                    not_a_target: true, ..
+                }) => format!("[/{}/]", label),
+                _ => format!("[{}]", label),
+            };
+            lines.push(format!("  {}{}", ids[target_name], node));
+        }
+        for (target_name, dependencies) in &self.edges {
+            for dependency in dependencies {
+                lines.push(format!("  {} --> {}", ids[target_name], ids[dependency]));
+            }
+            for dependency in self.order_only_edges.get(target_name).into_iter().flatten() {
+                lines.push(format!("  {} -.-> {}", ids[target_name], ids[dependency]));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+fn dot_id(target_name: &TargetName) -> String {
+    format!(
+        "\"{}\"",
+        target_name.0.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+fn mermaid_label(target_name: &TargetName) -> String {
+    format!("\"{}\"", target_name.0.replace('"', "#quot;"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::{TargetGraph, TargetName};
+
+    #[test]
+    fn test_export_subgraph() {
+        let phony = "#  Phony target (prerequisite of .PHONY).";
+        let database = format!("all: build | out\n{phony}\n\nbuild: lib.js\n{phony}\n\nlib.js: gen\n\ngen: src\n\n# Not a target:\nsrc:\n\nout:\n\nother:\n");
+        let target_graph = TargetGraph::try_from(&database).unwrap();
+        let roots = [TargetName("all".to_owned())];
+
+        let subgraph = target_graph.subgraph(&roots, Some(2), false);
+        assert_eq!(
+            subgraph.to_dot(),
+            r#"digraph mak {
+  "all" [label="all", style=dashed];
+  "build" [label="build", style=dashed];
+  "lib.js" [label="lib.js"];
+  "out" [label="out"];
+  "all" -> "build";
+  "all" -> "out" [style=dashed];
+  "build" -> "lib.js";
+}"#
+        );
+
+        let subgraph = target_graph.subgraph(&roots, None, true);
+        assert_eq!(
+            subgraph.to_mermaid(),
+            r#"flowchart TD
+  n0["lib.js"]
+  n1["gen"]
+  n2["out"]
+  n0 --> n1"#
+        );
+    }
+}
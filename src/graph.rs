@@ -0,0 +1,188 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::parse::{TargetGraph, TargetName};
+
+/// A dependency edge that was removed to break a cycle.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct DroppedCycle {
+    /// The path from the first target in the cycle back to itself, e.g. `[a, b, c, a]`.
+    pub(crate) path: Vec<TargetName>,
+}
+
+impl DroppedCycle {
+    /// Formatted similarly to `make`'s own message for this situation.
+    pub(crate) fn message(&self) -> String {
+        let path: Vec<String> = self.path.iter().map(|t| t.to_string()).collect();
+        format!(
+            "Circular {} <- {} dependency dropped: {}",
+            self.path[self.path.len() - 2],
+            self.path[self.path.len() - 1],
+            path.join(" → ")
+        )
+    }
+}
+
+impl TargetGraph {
+    /// Removes every dependency that would form a cycle, like `make` does.
+    /// Targets are visited depth-first starting from `root_target_names`, so
+    /// the dropped edge is the one that closes the cycle in that order.
+    pub(crate) fn break_cycles(&mut self, root_target_names: &[TargetName]) -> Vec<DroppedCycle> {
+        let mut cycle_breaker = CycleBreaker {
+            stack: vec![],
+            visited: HashSet::default(),
+            dropped_cycles: vec![],
+        };
+        for target_name in root_target_names {
+            cycle_breaker.visit(self, target_name);
+        }
+        cycle_breaker.dropped_cycles
+    }
+}
+
+impl TargetGraph {
+    /// Every target in the order that it can be built, i.e. after all its
+    /// dependencies, starting from `root_target_names` (depth-first, like
+    /// the scheduler). Assumes that cycles have been broken.
+    pub(crate) fn build_order(&self, root_target_names: &[TargetName]) -> Vec<TargetName> {
+        let mut visited = HashSet::<TargetName>::new();
+        let mut order = vec![];
+        for target_name in root_target_names {
+            self.visit_post_order(target_name, &mut visited, &mut order);
+        }
+        order
+    }
+
+    fn visit_post_order(
+        &self,
+        target_name: &TargetName,
+        visited: &mut HashSet<TargetName>,
+        order: &mut Vec<TargetName>,
+    ) {
+        let Some(dependencies) = self.all_dependencies(target_name) else {
+            return;
+        };
+        if !visited.insert(target_name.clone()) {
+            return;
+        }
+        for dependency in &dependencies {
+            self.visit_post_order(dependency, visited, order);
+        }
+        order.push(target_name.clone());
+    }
+
+    /// Everything that has to be built before any of `target_names`, in build order.
+    pub(crate) fn transitive_dependencies(&self, target_names: &[TargetName]) -> Vec<TargetName> {
+        let direct_dependencies: Vec<TargetName> = target_names
+            .iter()
+            .flat_map(|target_name| self.all_dependencies(target_name).unwrap_or_default())
+            .collect();
+        self.build_order(&direct_dependencies)
+    }
+
+    /// Everything that (transitively) depends on any of `target_names`, i.e.
+    /// what would need to be rebuilt if they changed, in build order.
+    pub(crate) fn transitive_dependents(&self, target_names: &[TargetName]) -> Vec<TargetName> {
+        let mut dependents = HashMap::<&TargetName, Vec<&TargetName>>::new();
+        for target_name in self.edges.keys() {
+            for dependency in self.all_dependencies(target_name).unwrap_or_default() {
+                if let Some((dependency, _)) = self.edges.get_key_value(&dependency) {
+                    dependents.entry(dependency).or_default().push(target_name);
+                }
+            }
+        }
+        let mut affected = HashSet::<&TargetName>::new();
+        let mut stack: Vec<&TargetName> = target_names.iter().collect();
+        while let Some(target_name) = stack.pop() {
+            for dependent in dependents.get(target_name).into_iter().flatten() {
+                if affected.insert(dependent) {
+                    stack.push(dependent);
+                }
+            }
+        }
+        let all_target_names: Vec<TargetName> = self.edges.keys().cloned().collect();
+        self.build_order(&all_target_names)
+            .into_iter()
+            .filter(|target_name| affected.contains(target_name))
+            .collect()
+    }
+}
+
+struct CycleBreaker {
+    stack: Vec<TargetName>,
+    visited: HashSet<TargetName>,
+    dropped_cycles: Vec<DroppedCycle>,
+}
+
+impl CycleBreaker {
+    fn visit(&mut self, target_graph: &mut TargetGraph, target_name: &TargetName) {
+        if !self.visited.insert(target_name.clone()) {
+            return;
+        }
+        self.stack.push(target_name.clone());
+        for dependency in target_graph
+            .all_dependencies(target_name)
+            .unwrap_or_default()
+        {
+            if let Some(cycle_start) = self.stack.iter().position(|t| *t == dependency) {
+                let mut path = self.stack[cycle_start..].to_vec();
+                path.push(dependency.clone());
+                self.dropped_cycles.push(DroppedCycle { path });
+                for edges in [&mut target_graph.edges, &mut target_graph.order_only_edges] {
+                    if let Some(dependencies) = edges.get_mut(target_name) {
+                        dependencies.retain(|t| *t != dependency);
+                    }
+                }
+                continue;
+            }
+            self.visit(target_graph, &dependency);
+        }
+        self.stack.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::{TargetGraph, TargetName};
+
+    #[test]
+    fn test_break_cycles() {
+        let database = "a: b\nb: c d\nc: a\nd:\n".to_owned();
+        let mut target_graph = TargetGraph::try_from(&database).unwrap();
+        let a = TargetName("a".to_owned());
+        let dropped_cycles = target_graph.break_cycles(&[a]);
+        assert_eq!(dropped_cycles.len(), 1);
+        assert_eq!(
+            dropped_cycles[0].message(),
+            "Circular c <- a dependency dropped: a → b → c → a"
+        );
+        assert_eq!(
+            target_graph.edges.get(&TargetName("c".to_owned())),
+            Some(&vec![])
+        );
+    }
+
+    #[test]
+    fn test_graph_queries() {
+        let database =
+            "all: lib test\nlib: gen\ntest: lib fixtures\ngen:\nfixtures:\nother: gen\n".to_owned();
+        let target_graph = TargetGraph::try_from(&database).unwrap();
+        let names = |names: &[&str]| -> Vec<TargetName> {
+            names
+                .iter()
+                .map(|name| TargetName(name.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            target_graph.build_order(&names(&["all"])),
+            names(&["gen", "lib", "fixtures", "test", "all"])
+        );
+        assert_eq!(
+            target_graph.transitive_dependencies(&names(&["test"])),
+            names(&["gen", "lib", "fixtures"])
+        );
+        assert_eq!(
+            target_graph.transitive_dependents(&names(&["gen"])),
+            names(&["lib", "test", "all", "other"])
+        );
+    }
+}
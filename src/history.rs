@@ -0,0 +1,227 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    events::millis,
+    parse::{TargetGraph, TargetName},
+};
+
+/// Relative to where `mak` runs rather than to `make`'s directory (from `-C`),
+/// like the logs. Entries are keyed by the full Makefile path, so they don't clash.
+const HISTORY_PATH: &str = ".mak/timings.json";
+
+/// Target durations from previous successful runs, stored in
+/// `.mak/timings.json` and keyed by Makefile path and then target name.
+pub(crate) struct TimingHistory {
+    makefile_key: String,
+    /// Milliseconds, by target name, by Makefile path.
+    all_durations: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl TimingHistory {
+    /// An unreadable history is treated as empty, since it is only used for estimates.
+    pub(crate) fn load(makefile_path: &Path) -> Self {
+        let makefile_key = makefile_path
+            .canonicalize()
+            .unwrap_or_else(|_| makefile_path.to_owned())
+            .to_string_lossy()
+            .into_owned();
+        let all_durations = fs::read_to_string(HISTORY_PATH)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        Self {
+            makefile_key,
+            all_durations,
+        }
+    }
+
+    pub(crate) fn expected_duration(&self, target_name: &TargetName) -> Option<Duration> {
+        self.all_durations
+            .get(&self.makefile_key)?
+            .get(&target_name.0)
+            .map(|millis| Duration::from_millis(*millis))
+    }
+
+    pub(crate) fn record(&mut self, target_name: &TargetName, duration: Duration) {
+        self.all_durations
+            .entry(self.makefile_key.clone())
+            .or_default()
+            .insert(target_name.0.clone(), millis(duration));
+    }
+
+    pub(crate) fn save(&self) {
+        let json = serde_json::to_string_pretty(&self.all_durations)
+            .expect("Could not serialize timing history.");
+        let path = PathBuf::from(HISTORY_PATH);
+        if let Err(e) = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, json))
+        {
+            eprintln!(
+                "Could not save timing history to {} ({})",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    /// The expected duration of the longest chain of targets from each
+    /// target up to one of `root_target_names` (including the target itself).
+    /// Starting the targets with the highest priority first keeps the
+    /// critical path moving when there are more targets than job slots.
+    pub(crate) fn priorities(
+        &self,
+        target_graph: &TargetGraph,
+        root_target_names: &[TargetName],
+    ) -> HashMap<TargetName, Duration> {
+        let mut dependents = HashMap::<TargetName, Vec<TargetName>>::new();
+        let mut visited = HashSet::<TargetName>::new();
+        let mut stack: Vec<TargetName> = root_target_names.to_vec();
+        while let Some(target_name) = stack.pop() {
+            if !visited.insert(target_name.clone()) {
+                continue;
+            }
+            for dependency in target_graph
+                .all_dependencies(&target_name)
+                .unwrap_or_default()
+            {
+                dependents
+                    .entry(dependency.clone())
+                    .or_default()
+                    .push(target_name.clone());
+                stack.push(dependency);
+            }
+        }
+
+        let mut priorities = HashMap::<TargetName, Duration>::new();
+        for target_name in &visited {
+            self.priority(target_name, &dependents, &mut priorities);
+        }
+        priorities
+    }
+
+    fn priority(
+        &self,
+        target_name: &TargetName,
+        dependents: &HashMap<TargetName, Vec<TargetName>>,
+        priorities: &mut HashMap<TargetName, Duration>,
+    ) -> Duration {
+        if let Some(priority) = priorities.get(target_name) {
+            return *priority;
+        }
+        let dependents_priority = dependents
+            .get(target_name)
+            .into_iter()
+            .flatten()
+            .map(|dependent| self.priority(dependent, dependents, priorities))
+            .max()
+            .unwrap_or_default();
+        let priority =
+            dependents_priority + self.expected_duration(target_name).unwrap_or_default();
+        priorities.insert(target_name.clone(), priority);
+        priority
+    }
+}
+
+/// Tracks how much work is left, based on the timing history, to estimate
+/// how long the rest of the build will take.
+pub(crate) struct BuildEstimate {
+    num_jobs: usize,
+    /// Targets that have not finished yet and have a known expected duration.
+    remaining: Mutex<HashMap<TargetName, (Duration, Option<Instant>)>>,
+}
+
+impl BuildEstimate {
+    /// Returns `None` if none of the targets have run before.
+    pub(crate) fn new(
+        timing_history: &TimingHistory,
+        target_names: impl Iterator<Item = TargetName>,
+        num_jobs: usize,
+    ) -> Option<Self> {
+        let remaining: HashMap<TargetName, (Duration, Option<Instant>)> = target_names
+            .filter_map(|target_name| {
+                let expected_duration = timing_history.expected_duration(&target_name)?;
+                Some((target_name, (expected_duration, None)))
+            })
+            .collect();
+        if remaining.is_empty() {
+            return None;
+        }
+        Some(Self {
+            num_jobs,
+            remaining: Mutex::new(remaining),
+        })
+    }
+
+    pub(crate) fn started(&self, target_name: &TargetName) {
+        if let Some((_, start_time)) = self
+            .remaining
+            .lock()
+            .expect("Could not access build estimate.")
+            .get_mut(target_name)
+        {
+            *start_time = Some(Instant::now());
+        }
+    }
+
+    pub(crate) fn finished(&self, target_name: &TargetName) {
+        self.remaining
+            .lock()
+            .expect("Could not access build estimate.")
+            .remove(target_name);
+    }
+
+    /// Assumes the remaining work is spread evenly across the job slots.
+    pub(crate) fn time_remaining(&self) -> Duration {
+        let remaining = self
+            .remaining
+            .lock()
+            .expect("Could not access build estimate.");
+        let remaining_work: Duration = remaining
+            .values()
+            .map(|(expected_duration, start_time)| match start_time {
+                Some(start_time) => expected_duration.saturating_sub(start_time.elapsed()),
+                None => *expected_duration,
+            })
+            .sum();
+        remaining_work / self.num_jobs as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, time::Duration};
+
+    use crate::parse::{TargetGraph, TargetName};
+
+    use super::TimingHistory;
+
+    #[test]
+    fn test_priorities() {
+        let database = "all: a b\na: c\nb: c d\nc:\nd:\n".to_owned();
+        let target_graph = TargetGraph::try_from(&database).unwrap();
+        let target_name = |name: &str| TargetName(name.to_owned());
+        let mut timing_history = TimingHistory {
+            makefile_key: "Makefile".to_owned(),
+            all_durations: BTreeMap::new(),
+        };
+        for (name, millis) in [("all", 1), ("a", 5), ("b", 2), ("c", 3)] {
+            timing_history.record(&target_name(name), Duration::from_millis(millis));
+        }
+        let priorities = timing_history.priorities(&target_graph, &[target_name("all")]);
+        let priority = |name: &str| priorities[&target_name(name)].as_millis();
+        assert_eq!(priority("all"), 1);
+        assert_eq!(priority("a"), 6);
+        assert_eq!(priority("b"), 3);
+        // `d` has never run.
+        assert_eq!(priority("d"), 3);
+        assert_eq!(priority("c"), 9);
+    }
+}
@@ -0,0 +1,156 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::thread::available_parallelism;
+use std::time::Duration;
+
+use async_std::channel::{bounded, Sender};
+
+/// A counting semaphore that limits how many `make` invocations run at once.
+///
+/// When no slot is available, waiting targets are queued by priority (e.g. the
+/// expected duration of the longest chain of targets that depends on them), so
+/// that a freed slot goes to the target that is most likely to hold up the build.
+#[derive(Clone)]
+pub(crate) struct JobSlots {
+    state: Arc<Mutex<JobSlotsState>>,
+}
+
+struct JobSlotsState {
+    num_available: usize,
+    num_queued: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+/// A target waiting for a slot, which is handed over by sending on `sender`.
+struct Waiter {
+    priority: Duration,
+    /// Targets with the same priority are served in the order they started waiting.
+    queue_position: Reverse<u64>,
+    sender: Sender<()>,
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.queue_position).cmp(&(other.priority, other.queue_position))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+pub(crate) struct JobSlot {
+    state: Arc<Mutex<JobSlotsState>>,
+}
+
+impl JobSlots {
+    pub(crate) fn new(num_jobs: NonZeroUsize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(JobSlotsState {
+                num_available: num_jobs.get(),
+                num_queued: 0,
+                waiters: BinaryHeap::new(),
+            })),
+        }
+    }
+
+    /// Waits for a slot. Higher priorities are served first.
+    pub(crate) async fn acquire(&self, priority: Duration) -> JobSlot {
+        let receiver = {
+            let mut state = self.state.lock().expect("Could not access job slots.");
+            if state.num_available > 0 {
+                state.num_available -= 1;
+                return JobSlot {
+                    state: self.state.clone(),
+                };
+            }
+            let (sender, receiver) = bounded(1);
+            let queue_position = Reverse(state.num_queued);
+            state.num_queued += 1;
+            state.waiters.push(Waiter {
+                priority,
+                queue_position,
+                sender,
+            });
+            receiver
+        };
+        receiver
+            .recv()
+            .await
+            .expect("Job slots were unexpectedly closed.");
+        JobSlot {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl Drop for JobSlot {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().expect("Could not access job slots.");
+        while let Some(waiter) = state.waiters.pop() {
+            // This only fails if the waiter has stopped waiting, in which case the slot goes to the next one.
+            if waiter.sender.try_send(()).is_ok() {
+                return;
+            }
+        }
+        state.num_available += 1;
+    }
+}
+
+pub(crate) fn default_num_jobs() -> NonZeroUsize {
+    available_parallelism().unwrap_or(NonZeroUsize::MIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroUsize,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use async_std::task::{block_on, spawn, yield_now};
+    use futures::future::join_all;
+
+    use super::JobSlots;
+
+    #[test]
+    fn test_job_slot_priority() {
+        let job_slots = JobSlots::new(NonZeroUsize::MIN);
+        let order = Arc::new(Mutex::new(vec![]));
+        block_on(async {
+            let job_slot = job_slots.acquire(Duration::ZERO).await;
+            let handles: Vec<_> = [1, 3, 2, 3]
+                .into_iter()
+                .enumerate()
+                .map(|(i, priority)| {
+                    let job_slots = job_slots.clone();
+                    let order = order.clone();
+                    spawn(async move {
+                        let _job_slot = job_slots.acquire(Duration::from_secs(priority)).await;
+                        order.lock().unwrap().push(i);
+                    })
+                })
+                .collect();
+            // Let every task start waiting.
+            while job_slots.state.lock().unwrap().waiters.len() < 4 {
+                yield_now().await;
+            }
+            drop(job_slot);
+            join_all(handles).await;
+        });
+        assert_eq!(*order.lock().unwrap(), vec![1, 3, 2, 0]);
+    }
+}
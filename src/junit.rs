@@ -0,0 +1,196 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{parse::TargetName, OutputLine, TargetStatus};
+
+struct TestCase {
+    target_name: TargetName,
+    status: TargetStatus,
+    duration: Duration,
+    stdout: String,
+    stderr: String,
+}
+
+/// Collects the result of every scheduled target, to be written as a JUnit
+/// XML report (one test case per target) for CI dashboards.
+pub(crate) struct JunitReport {
+    start_time: Instant,
+    test_cases: Mutex<Vec<TestCase>>,
+}
+
+impl JunitReport {
+    pub(crate) fn new(start_time: Instant) -> Self {
+        Self {
+            start_time,
+            test_cases: Mutex::new(vec![]),
+        }
+    }
+
+    pub(crate) fn record(
+        &self,
+        target_name: &TargetName,
+        status: TargetStatus,
+        duration: Duration,
+        output_lines: &[OutputLine],
+    ) {
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+        for output_line in output_lines {
+            let (output, line) = match output_line {
+                OutputLine::Stdout(line) => (&mut stdout, line),
+                OutputLine::Stderr(line) => (&mut stderr, line),
+            };
+            output.push_str(line);
+            output.push('\n');
+        }
+        self.test_cases
+            .lock()
+            .expect("Could not access JUnit test cases.")
+            .push(TestCase {
+                target_name: target_name.clone(),
+                status,
+                duration,
+                stdout,
+                stderr,
+            });
+    }
+
+    fn to_xml(&self) -> String {
+        let test_cases = self
+            .test_cases
+            .lock()
+            .expect("Could not access JUnit test cases.");
+        let count = |statuses: &[TargetStatus]| {
+            test_cases
+                .iter()
+                .filter(|test_case| statuses.contains(&test_case.status))
+                .count()
+        };
+        let mut xml = String::new();
+        // `write!` to a `String` cannot fail.
+        let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(
+            xml,
+            r#"<testsuites><testsuite name="mak" tests="{}" failures="{}" errors="0" skipped="{}" time="{:.3}">"#,
+            test_cases.len(),
+            count(&[TargetStatus::Failed]),
+            count(&[TargetStatus::Skipped, TargetStatus::Cancelled]),
+            (Instant::now() - self.start_time).as_secs_f64()
+        );
+        for test_case in test_cases.iter() {
+            let _ = write!(
+                xml,
+                r#"  <testcase classname="mak" name="{}" time="{:.3}">"#,
+                escape_xml(&test_case.target_name.0),
+                test_case.duration.as_secs_f64()
+            );
+            match test_case.status {
+                TargetStatus::Succeeded => {}
+                TargetStatus::UpToDate => {
+                    let _ = write!(xml, "<system-out>Up to date.</system-out>");
+                }
+                TargetStatus::Failed => {
+                    let _ = write!(xml, r#"<failure message="Target failed."/>"#);
+                }
+                TargetStatus::Skipped => {
+                    let _ = write!(xml, r#"<skipped message="A dependency failed."/>"#);
+                }
+                TargetStatus::Cancelled => {
+                    let _ = write!(xml, r#"<skipped message="The build was cancelled."/>"#);
+                }
+            }
+            if !test_case.stdout.is_empty() {
+                let _ = write!(
+                    xml,
+                    "<system-out>{}</system-out>",
+                    escape_xml(&test_case.stdout)
+                );
+            }
+            if !test_case.stderr.is_empty() {
+                let _ = write!(
+                    xml,
+                    "<system-err>{}</system-err>",
+                    escape_xml(&test_case.stderr)
+                );
+            }
+            let _ = writeln!(xml, "</testcase>");
+        }
+        let _ = writeln!(xml, "</testsuite></testsuites>");
+        xml
+    }
+
+    pub(crate) fn write(&self, path: &Path) {
+        if let Err(e) = fs::write(path, self.to_xml()) {
+            eprintln!("Could not write JUnit report to {} ({})", path.display(), e);
+        }
+    }
+}
+
+/// Escapes text for use in XML content or attributes, dropping control
+/// characters that XML 1.0 does not allow at all (e.g. from colored output).
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{parse::TargetName, OutputLine, TargetStatus};
+
+    use super::JunitReport;
+
+    #[test]
+    fn test_junit_report() {
+        let junit_report = JunitReport::new(Instant::now());
+        junit_report.record(
+            &TargetName("build".to_owned()),
+            TargetStatus::Succeeded,
+            Duration::from_millis(1500),
+            &[OutputLine::Stdout("ok".to_owned())],
+        );
+        junit_report.record(
+            &TargetName("test<&>".to_owned()),
+            TargetStatus::Failed,
+            Duration::from_millis(250),
+            &[OutputLine::Stderr("\u{1b}[31mfailed\u{1b}[0m".to_owned())],
+        );
+        junit_report.record(
+            &TargetName("deploy".to_owned()),
+            TargetStatus::Skipped,
+            Duration::ZERO,
+            &[],
+        );
+        let xml = junit_report.to_xml();
+        assert!(xml.contains(r#"tests="3" failures="1" errors="0" skipped="1""#));
+        assert!(xml.contains(
+            r#"<testcase classname="mak" name="build" time="1.500"><system-out>ok
+</system-out></testcase>"#
+        ));
+        assert!(xml.contains(
+            r#"<testcase classname="mak" name="test&lt;&amp;&gt;" time="0.250"><failure message="Target failed."/><system-err>[31mfailed[0m
+</system-err></testcase>"#
+        ));
+        assert!(xml.contains(
+            r#"<testcase classname="mak" name="deploy" time="0.000"><skipped message="A dependency failed."/></testcase>"#
+        ));
+    }
+}
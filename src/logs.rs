@@ -0,0 +1,135 @@
+use std::{
+    fs::{create_dir_all, read_dir, remove_dir_all, File},
+    io::{stdout, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::exit,
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{parse::TargetName, OutputLine};
+
+const LOGS_DIRECTORY: &str = ".mak/logs";
+/// Logs from older runs are removed when a new run starts.
+const NUM_RUNS_TO_KEEP: usize = 10;
+
+/// The log directory for a single run of `mak`, e.g. `.mak/logs/1697000000000/`.
+///
+/// Run directories are named by their start time in (zero-padded) milliseconds
+/// since the epoch, so that they sort chronologically.
+pub(crate) struct RunLogs {
+    directory: PathBuf,
+}
+
+impl RunLogs {
+    /// Returns `None` (after printing a warning) if the directory could not be created.
+    pub(crate) fn create() -> Option<Self> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System time is before the epoch.")
+            .as_millis();
+        let directory = Path::new(LOGS_DIRECTORY).join(format!("{:013}", millis));
+        if let Err(e) = create_dir_all(&directory) {
+            eprintln!(
+                "Could not create log directory at {} ({}), continuing without logs.",
+                directory.display(),
+                e
+            );
+            return None;
+        }
+        prune_old_runs();
+        Some(Self { directory })
+    }
+
+    pub(crate) fn target_log(&self, target_name: &TargetName) -> Option<TargetLog> {
+        let path = self.directory.join(log_file_name(target_name));
+        let file = File::create(&path).ok()?;
+        Some(TargetLog {
+            path,
+            writer: Mutex::new(BufWriter::new(file)),
+            start_time: Instant::now(),
+        })
+    }
+}
+
+/// Interleaved stdout and stderr of a single target, with each line tagged by
+/// its stream and time since the target started.
+pub(crate) struct TargetLog {
+    pub(crate) path: PathBuf,
+    writer: Mutex<BufWriter<File>>,
+    start_time: Instant,
+}
+
+impl TargetLog {
+    pub(crate) fn write(&self, output_line: &OutputLine) {
+        let elapsed = Instant::now() - self.start_time;
+        let (stream, line) = match output_line {
+            OutputLine::Stdout(line) => ("stdout", line),
+            OutputLine::Stderr(line) => ("stderr", line),
+        };
+        let mut writer = self.writer.lock().expect("Could not access log file.");
+        let _ = writeln!(
+            writer,
+            "[{:02}:{:06.3}] [{}] {}",
+            elapsed.as_secs() / 60,
+            elapsed.as_secs_f64() % 60.0,
+            stream,
+            line
+        );
+    }
+
+    pub(crate) fn flush(&self) {
+        let _ = self
+            .writer
+            .lock()
+            .expect("Could not access log file.")
+            .flush();
+    }
+}
+
+/// Target names can contain `/`, so it (and `%`, to keep names unambiguous) is percent-encoded.
+fn log_file_name(target_name: &TargetName) -> String {
+    format!(
+        "{}.log",
+        target_name.0.replace('%', "%25").replace('/', "%2F")
+    )
+}
+
+/// Run directories, oldest first.
+fn run_directories() -> Vec<PathBuf> {
+    let Ok(entries) = read_dir(LOGS_DIRECTORY) else {
+        return vec![];
+    };
+    let mut directories: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    directories.sort();
+    directories
+}
+
+fn prune_old_runs() {
+    let directories = run_directories();
+    let num_to_remove = directories.len().saturating_sub(NUM_RUNS_TO_KEEP);
+    for directory in &directories[..num_to_remove] {
+        // Ignore failures, e.g. if another `mak` process removed it first.
+        let _ = remove_dir_all(directory);
+    }
+}
+
+/// Prints the log from the most recent run that built the given target.
+pub(crate) fn print_latest_log(target_name: &str) -> ! {
+    let file_name = log_file_name(&TargetName(target_name.to_owned()));
+    for directory in run_directories().iter().rev() {
+        let path = directory.join(&file_name);
+        let Ok(contents) = std::fs::read(&path) else {
+            continue;
+        };
+        eprintln!("Log: {}", path.display());
+        stdout().write_all(&contents).expect("Could not print log.");
+        exit(0);
+    }
+    eprintln!("No log found for target: {}", target_name);
+    exit(1);
+}
@@ -1,19 +1,49 @@
 use async_std::task::{self, block_on, JoinHandle};
+use cancel::Cancellation;
+use describe::{makefile_list, TargetDescriptions};
+use events::{millis, BuildEvent, EventLog, JsonRenderer};
 use futures::{future::join_all, FutureExt};
-use indexmap::IndexMap;
-use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
+use history::{BuildEstimate, TimingHistory};
+use jobs::{default_num_jobs, JobSlots};
+use junit::JunitReport;
+use logs::{print_latest_log, RunLogs, TargetLog};
+use picker::{can_pick_target, pick_target};
+use render::{FancyRenderer, HiddenRenderer, PlainRenderer, Renderer, TargetRenderer};
+use serde::Serialize;
+use timings::TargetTimings;
+use trace::BuildTrace;
+use watch::FileWatcher;
+mod cancel;
+mod completions;
+mod describe;
+mod events;
+mod export;
+mod graph;
+mod history;
+mod jobs;
+mod junit;
+mod logs;
 mod options;
+mod patterns;
+mod picker;
+mod render;
+mod timings;
+mod trace;
+mod up_to_date;
+mod watch;
 use std::{
-    collections::HashMap,
-    io::{BufRead, BufReader},
-    path::Path,
+    collections::{HashMap, HashSet},
+    io::{stderr, BufRead, BufReader, IsTerminal, Read},
+    os::unix::process::CommandExt,
+    path::PathBuf,
     process::{exit, Command, Stdio},
-    sync::{mpsc, Arc},
+    sync::{mpsc, Arc, Mutex},
     time::{Duration, Instant},
 };
 
-use options::{get_options, MakArgs};
+use options::{get_options, GraphFormat, GraphQuery, MakArgs, OutputFormat, Progress};
 use parse::TargetName;
+use up_to_date::{find_up_to_date_targets, newer_dependencies};
 
 use crate::parse::TargetGraph;
 
@@ -34,23 +64,29 @@ fn makefile_not_found(options: &MakArgs) {
 fn main() {
     let start_time = Instant::now();
     let options = get_options();
+    if let Some(target_name) = &options.show_log {
+        print_latest_log(target_name);
+    }
 
-    let mut args = vec!["-pRrq".to_owned()];
     let makefile_path_str = options.makefile_path.as_ref().map(|p| {
         p.to_str()
             .expect("Could not convert Makefile path to a string.")
             .to_owned()
     });
+    let make_directory = options.make_directory().unwrap_or_default();
     if let Some(some_makefile_path_str) = &makefile_path_str {
-        let path = Path::new(&some_makefile_path_str);
+        let path = make_directory.join(some_makefile_path_str);
         if !path.exists() {
             makefile_not_found(&options);
         }
-        args.append(&mut make_args(&makefile_path_str));
-    } else if !Path::new("makefile").exists() && !Path::new("Makefile").exists() {
+    } else if !make_directory.join("makefile").exists() && !make_directory.join("Makefile").exists()
+    {
         makefile_not_found(&options);
     }
+    let make_args = make_args(&makefile_path_str, &options);
 
+    let mut args = vec!["-pRrq".to_owned()];
+    args.extend(make_args.iter().cloned());
     let child = Command::new("make")
         .args(args)
         .stdout(Stdio::piped())
@@ -61,24 +97,76 @@ fn main() {
         .expect(ERROR_COULD_NOT_LIST_TARGETS);
 
     let stdout_str = String::from_utf8(output.stdout).expect(ERROR_COULD_NOT_LIST_TARGETS);
-    let mut target_graph: TargetGraph =
-        TargetGraph::try_from(&stdout_str).expect("Could not parse targets");
-    target_graph.edges = IndexMap::from_iter(target_graph.edges.into_iter().filter(|edge| {
-        !edge.0 .0.starts_with('.') && makefile_path_str != Some(edge.0 .0.clone())
-    }));
+    let mut target_graph = match TargetGraph::try_from(&stdout_str) {
+        Ok(target_graph) => target_graph,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("This is likely a bug in `mak`. Please report it along with the Makefile.");
+            exit(1)
+        }
+    };
+    let is_listed_target = |target_name: &TargetName| {
+        !target_name.0.starts_with('.') && makefile_path_str != Some(target_name.0.clone())
+    };
+    target_graph.retain_targets(is_listed_target);
+    let file_exists = |target_name: &TargetName| make_directory.join(&target_name.0).exists();
+    target_graph.resolve_pattern_prerequisites(&file_exists);
 
     if options.print_graph {
-        println!(
-            "{}",
-            serde_json::to_string_pretty(&target_graph).expect("Could not print graph")
-        );
+        let root_target_names: Vec<TargetName> = options
+            .targets
+            .iter()
+            .map(|target_string| {
+                let target_name = TargetName(target_string.to_owned());
+                if !target_graph.resolve_pattern_target(&target_name, &file_exists) {
+                    eprintln!("Unknown target specified: {}", target_name);
+                    exit(1)
+                };
+                target_name
+            })
+            .collect();
+        let subgraph =
+            target_graph.subgraph(&root_target_names, options.graph_depth, options.hide_phony);
+        match options.graph_format {
+            GraphFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&subgraph).expect("Could not print graph")
+            ),
+            GraphFormat::Dot => println!("{}", subgraph.to_dot()),
+            GraphFormat::Mermaid => println!("{}", subgraph.to_mermaid()),
+        }
+        exit(0)
+    }
+    if options.list {
+        let target_descriptions =
+            TargetDescriptions::read(&makefile_list(&stdout_str, &make_directory));
+        match options.output_format {
+            OutputFormat::Text => target_descriptions.print_list(&target_graph),
+            OutputFormat::Json => target_descriptions.print_list_json(&target_graph),
+        }
         exit(0)
     }
     if options.print_completion_targets {
+        let target_descriptions =
+            TargetDescriptions::read(&makefile_list(&stdout_str, &make_directory));
         let lines: Vec<String> = target_graph
             .edges
             .keys()
-            .map(|target_name| target_name.to_string())
+            // Source files (including the Makefile itself) can't be built.
+            .filter(|target_name| {
+                !target_graph
+                    .metadata
+                    .get(*target_name)
+                    .is_some_and(|metadata| metadata.not_a_target)
+            })
+            .cloned()
+            .chain(target_graph.pattern_target_candidates(&make_directory))
+            .map(
+                |target_name| match target_descriptions.description(&target_name) {
+                    Some(description) => format!("{}\t{}", target_name, description),
+                    None => target_name.to_string(),
+                },
+            )
             .collect();
         for line in lines {
             println!("{}", line);
@@ -87,9 +175,22 @@ fn main() {
     }
 
     let target_names: Vec<TargetName> = if options.targets.is_empty() {
-        let default_target_name = match &target_graph.default_goal {
-            Some(target_name) => target_name.clone(),
-            None => {
+        let default_target_name = match (&target_graph.default_goal, options.pick) {
+            (Some(target_name), false) => target_name.clone(),
+            (_, pick) if pick || can_pick_target() => {
+                if !can_pick_target() {
+                    eprintln!("Picking a target requires an interactive terminal");
+                    exit(1)
+                }
+                match pick_target(&target_graph) {
+                    Some(target_name) => target_name,
+                    None => {
+                        eprintln!("No target picked");
+                        exit(1)
+                    }
+                }
+            }
+            _ => {
                 eprintln!("No target specified and no default target available");
                 exit(1)
             }
@@ -101,7 +202,7 @@ fn main() {
             .iter()
             .map(|target_string| {
                 let target_name = TargetName(target_string.to_owned());
-                if !target_graph.edges.contains_key(&target_name) {
+                if !target_graph.resolve_pattern_target(&target_name, &file_exists) {
                     eprintln!("Unknown target specified: {}", target_name);
                     exit(1)
                 };
@@ -110,49 +211,399 @@ fn main() {
             .collect()
     };
 
-    let multi_progress = Arc::new(MultiProgress::new());
+    // Scheduling relies on the graph being acyclic.
+    for dropped_cycle in target_graph.break_cycles(&target_names) {
+        eprintln!("{}", dropped_cycle.message());
+    }
+
+    if let Some(graph_query) = options.query {
+        let result = match graph_query {
+            GraphQuery::Dependencies => target_graph.transitive_dependencies(&target_names),
+            GraphQuery::Dependents => target_graph.transitive_dependents(&target_names),
+            GraphQuery::BuildOrder => target_graph.build_order(&target_names),
+        };
+        match options.output_format {
+            OutputFormat::Text => {
+                for target_name in result {
+                    println!("{}", target_name);
+                }
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&result).expect("Could not print query result")
+            ),
+        }
+        exit(0)
+    }
+
+    let up_to_date_targets = find_up_to_date_targets(&target_graph, &make_directory);
+    let num_jobs = options.jobs.unwrap_or_else(default_num_jobs);
+    let mut timing_history =
+        TimingHistory::load(&make_directory.join(makefile_path_str.as_deref().unwrap_or(
+            if make_directory.join("makefile").exists() {
+                "makefile"
+            } else {
+                "Makefile"
+            },
+        )));
+    let priorities = timing_history.priorities(&target_graph, &target_names);
+    let build_estimate = estimate_build(
+        &options,
+        &timing_history,
+        &priorities,
+        &up_to_date_targets,
+        num_jobs.get(),
+    );
+
+    let events =
+        (options.output_format == OutputFormat::Json).then(|| Arc::new(EventLog::new(start_time)));
+    let renderer: Arc<dyn Renderer> = match (&events, options.progress) {
+        // Only JSON should be printed to stdout, and output lines are already included as events.
+        (Some(events), _) => Arc::new(JsonRenderer {
+            events: events.clone(),
+        }),
+        (None, Progress::Fancy) => {
+            Arc::new(FancyRenderer::new(options.stream_output, build_estimate))
+        }
+        (None, Progress::Auto) if stderr().is_terminal() => {
+            Arc::new(FancyRenderer::new(options.stream_output, build_estimate))
+        }
+        (None, Progress::Auto | Progress::Plain) => {
+            Arc::new(PlainRenderer::new(start_time, options.stream_output))
+        }
+        (None, Progress::None) => Arc::new(HiddenRenderer {
+            stream_output: options.stream_output,
+        }),
+    };
+    let cancellation = Arc::new(Cancellation::default());
+    cancellation.forward_signals();
 
     let mut shared_make = SharedMake {
-        multi_progress: multi_progress.clone(),
+        renderer,
         futures: HashMap::default(),
         target_graph,
-        makefile_path_str,
+        up_to_date_targets,
+        make_args,
+        make_directory: make_directory.clone(),
+        dry_run: options.dry_run,
+        keep_going: options.keep_going,
+        failures: Arc::new(Mutex::new(vec![])),
+        cancellation: cancellation.clone(),
+        job_slots: JobSlots::new(num_jobs),
+        priorities,
+        // Dry runs don't produce output worth keeping, and would rotate out real logs.
+        run_logs: (!options.dry_run)
+            .then(RunLogs::create)
+            .flatten()
+            .map(Arc::new),
+        junit_report: options
+            .junit
+            .is_some()
+            .then(|| Arc::new(JunitReport::new(start_time))),
+        build_trace: options
+            .trace
+            .is_some()
+            .then(|| Arc::new(BuildTrace::new(start_time))),
+        target_timings: Arc::new(TargetTimings::default()),
     };
 
-    block_on(shared_make.make_targets(&target_names));
-    let num_main_targets = target_names.len();
-    let num_dependencies = shared_make.futures.len() - num_main_targets;
-    if options.dry_run {
-        println!(
-            "Dry run found {} target{} and {} additional dependenc{} in {:?}",
-            num_main_targets,
-            if num_main_targets == 1 { "" } else { "s" },
-            num_dependencies,
-            if num_dependencies == 1 { "y" } else { "ies" },
-            Instant::now() - start_time
+    let file_watcher = options.watch.then(|| {
+        let source_files = shared_make
+            .target_graph
+            .source_files(&target_names, &make_directory);
+        match FileWatcher::new(&source_files, &make_directory) {
+            Ok(file_watcher) => file_watcher,
+            Err(e) => {
+                eprintln!("Could not watch files for changes ({})", e);
+                exit(1)
+            }
+        }
+    });
+
+    let mut build_start_time = start_time;
+    loop {
+        block_on(shared_make.make_targets(&target_names));
+        let exit_code = shared_make.finish_build(
+            &options,
+            &target_names,
+            events.as_deref(),
+            &mut timing_history,
+            build_start_time,
         );
-    } else {
-        println!(
-            "Built {} target{} and {} additional dependenc{} in {:?}",
-            num_main_targets,
-            if num_main_targets == 1 { "" } else { "s" },
-            num_dependencies,
-            if num_dependencies == 1 { "y" } else { "ies" },
-            Instant::now() - start_time
+        let Some(file_watcher) = &file_watcher else {
+            exit(exit_code)
+        };
+        // Stop watching if the build was interrupted by a signal.
+        if cancellation.signal().is_some() {
+            exit(exit_code)
+        }
+        cancellation.reset();
+        eprintln!(
+            "👀 Watching {} file{} for changes…",
+            file_watcher.num_files(),
+            if file_watcher.num_files() == 1 {
+                ""
+            } else {
+                "s"
+            }
+        );
+        let Some(changed_files) = file_watcher.wait_for_changes(&cancellation) else {
+            exit(128 + cancellation.signal().unwrap_or_default())
+        };
+        let changed_file_names: Vec<String> = changed_files
+            .iter()
+            .map(|target_name| target_name.to_string())
+            .collect();
+        eprintln!("🔄 Changed: {}", changed_file_names.join(", "));
+        build_start_time = Instant::now();
+        shared_make.prepare_rebuild(&changed_files, &target_names, build_start_time);
+        shared_make.renderer.build_started(
+            build_start_time,
+            estimate_build(
+                &options,
+                &timing_history,
+                &shared_make.priorities,
+                &shared_make.up_to_date_targets,
+                num_jobs.get(),
+            ),
         );
     }
 }
 
-type SharedFuture = futures::future::Shared<JoinHandle<()>>;
+/// Based on previous runs of the targets that will be (re)built. Dry runs have no estimate.
+fn estimate_build(
+    options: &MakArgs,
+    timing_history: &TimingHistory,
+    priorities: &HashMap<TargetName, Duration>,
+    up_to_date_targets: &HashSet<TargetName>,
+    num_jobs: usize,
+) -> Option<Arc<BuildEstimate>> {
+    if options.dry_run {
+        return None;
+    }
+    BuildEstimate::new(
+        timing_history,
+        priorities
+            .keys()
+            .filter(|target_name| !up_to_date_targets.contains(target_name))
+            .cloned(),
+        num_jobs,
+    )
+    .map(Arc::new)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum TargetStatus {
+    Succeeded,
+    /// Did not need to be remade, so `make` was not invoked.
+    UpToDate,
+    Failed,
+    /// Not attempted, because a dependency failed or was skipped.
+    Skipped,
+    /// Stopped or not attempted, because the build was cancelled.
+    Cancelled,
+}
+
+struct TargetFailure {
+    target_name: TargetName,
+    output_lines: Vec<OutputLine>,
+    log_path: Option<PathBuf>,
+}
+
+impl TargetStatus {
+    fn is_success(&self) -> bool {
+        matches!(self, TargetStatus::Succeeded | TargetStatus::UpToDate)
+    }
+}
+
+type SharedFuture = futures::future::Shared<JoinHandle<TargetStatus>>;
 
 struct SharedMake {
-    multi_progress: Arc<MultiProgress>,
+    renderer: Arc<dyn Renderer>,
     futures: HashMap<TargetName, SharedFuture>,
     target_graph: TargetGraph,
-    makefile_path_str: Option<String>,
+    up_to_date_targets: HashSet<TargetName>,
+    /// Passed to every `make` invocation.
+    make_args: Vec<String>,
+    /// Where `make` runs (from `-C`), which file paths are relative to.
+    make_directory: PathBuf,
+    dry_run: bool,
+    keep_going: bool,
+    failures: Arc<Mutex<Vec<TargetFailure>>>,
+    cancellation: Arc<Cancellation>,
+    job_slots: JobSlots,
+    /// Targets with a higher priority get job slots first.
+    priorities: HashMap<TargetName, Duration>,
+    run_logs: Option<Arc<RunLogs>>,
+    junit_report: Option<Arc<JunitReport>>,
+    build_trace: Option<Arc<BuildTrace>>,
+    target_timings: Arc<TargetTimings>,
 }
 
 impl SharedMake {
+    /// Records and reports the results of a build. Returns the exit code.
+    fn finish_build(
+        &mut self,
+        options: &MakArgs,
+        target_names: &[TargetName],
+        events: Option<&EventLog>,
+        timing_history: &mut TimingHistory,
+        start_time: Instant,
+    ) -> i32 {
+        self.renderer.build_finished();
+        if !options.dry_run {
+            for (target_name, future) in &self.futures {
+                if future.peek() == Some(&TargetStatus::Succeeded) {
+                    timing_history.record(target_name, self.target_timings.duration(target_name));
+                }
+            }
+            timing_history.save();
+        }
+        if let (Some(junit_report), Some(junit_path)) = (&self.junit_report, &options.junit) {
+            junit_report.write(junit_path);
+        }
+        if let (Some(build_trace), Some(trace_path)) = (&self.build_trace, &options.trace) {
+            build_trace.write(trace_path);
+        }
+        let num_main_targets = target_names.len();
+        let num_dependencies = self.futures.len() - num_main_targets;
+
+        let failures = std::mem::take(
+            &mut *self
+                .failures
+                .lock()
+                .expect("Could not access target failures."),
+        );
+        let count_status = |status: TargetStatus| {
+            self.futures
+                .values()
+                .filter(|future| future.peek() == Some(&status))
+                .count()
+        };
+        let num_up_to_date = count_status(TargetStatus::UpToDate);
+        let num_cancelled = count_status(TargetStatus::Cancelled);
+        let num_skipped = count_status(TargetStatus::Skipped);
+        if let Some(events) = events {
+            events.emit(BuildEvent::BuildFinished {
+                success: failures.is_empty() && self.cancellation.signal().is_none(),
+                duration_ms: millis(Instant::now() - start_time),
+                num_targets: self.futures.len(),
+                num_up_to_date,
+                num_skipped,
+                num_cancelled,
+                failed_targets: failures
+                    .iter()
+                    .map(|failure| &failure.target_name)
+                    .collect(),
+            });
+            if !failures.is_empty() {
+                return 1;
+            }
+            if let Some(signal) = self.cancellation.signal() {
+                return 128 + signal;
+            }
+            return 0;
+        }
+        if options.timings {
+            self.target_timings.print_summary(
+                &self.target_graph,
+                target_names,
+                Instant::now() - start_time,
+            );
+        }
+        if !failures.is_empty() {
+            for failure in &failures {
+                print_failure(failure);
+            }
+            println!(
+                "{} target{} failed, {} {} cancelled, and {} {} skipped:",
+                failures.len(),
+                if failures.len() == 1 { "" } else { "s" },
+                num_cancelled,
+                if num_cancelled == 1 { "was" } else { "were" },
+                num_skipped,
+                if num_skipped == 1 { "was" } else { "were" },
+            );
+            for failure in &failures {
+                println!("❌ {}", failure.target_name);
+            }
+            return 1;
+        }
+        if let Some(signal) = self.cancellation.signal() {
+            println!(
+                "Cancelled {} target{} after receiving a signal",
+                num_cancelled,
+                if num_cancelled == 1 { "" } else { "s" },
+            );
+            return 128 + signal;
+        }
+
+        let up_to_date_suffix = if num_up_to_date > 0 {
+            format!(" ({} already up to date)", num_up_to_date)
+        } else {
+            "".to_owned()
+        };
+        if options.dry_run {
+            println!(
+                "Dry run found {} target{} and {} additional dependenc{}{} in {:?}",
+                num_main_targets,
+                if num_main_targets == 1 { "" } else { "s" },
+                num_dependencies,
+                if num_dependencies == 1 { "y" } else { "ies" },
+                up_to_date_suffix,
+                Instant::now() - start_time
+            );
+        } else {
+            println!(
+                "Built {} target{} and {} additional dependenc{}{} in {:?}",
+                num_main_targets,
+                if num_main_targets == 1 { "" } else { "s" },
+                num_dependencies,
+                if num_dependencies == 1 { "y" } else { "ies" },
+                up_to_date_suffix,
+                Instant::now() - start_time
+            );
+        }
+        0
+    }
+
+    /// Resets the state from the previous build, so that only targets
+    /// affected by `changed_files` (or that did not succeed) are rebuilt.
+    fn prepare_rebuild(
+        &mut self,
+        changed_files: &[TargetName],
+        target_names: &[TargetName],
+        start_time: Instant,
+    ) {
+        let affected_targets: HashSet<TargetName> = self
+            .target_graph
+            .transitive_dependents(changed_files)
+            .into_iter()
+            .collect();
+        self.up_to_date_targets = self
+            .target_graph
+            .build_order(target_names)
+            .into_iter()
+            .filter(|target_name| {
+                !affected_targets.contains(target_name)
+                    && self
+                        .futures
+                        .get(target_name)
+                        .and_then(|future| future.peek())
+                        .is_some_and(TargetStatus::is_success)
+            })
+            .collect();
+        self.futures.clear();
+        if self.junit_report.is_some() {
+            self.junit_report = Some(Arc::new(JunitReport::new(start_time)));
+        }
+        if self.build_trace.is_some() {
+            self.build_trace = Some(Arc::new(BuildTrace::new(start_time)));
+        }
+        self.target_timings = Arc::new(TargetTimings::default());
+    }
+
     async fn make_targets(&mut self, target_names: &[TargetName]) {
         join_all(
             target_names
@@ -168,101 +619,170 @@ impl SharedMake {
             return sender.clone();
         }
 
-        let Some(dependencies) = self.target_graph.edges.get(target_name) else {
+        // Order-only dependencies are scheduled like normal ones. They are all
+        // passed to `make` using `-o`, so only normal dependencies that are
+        // newer than the target (see below) make it out of date.
+        let Some(dependencies) = self.target_graph.all_dependencies(target_name) else {
             eprintln!(
                 "Internal error: Unexpectedly missing a target: {}",
                 target_name
             );
             exit(1);
         };
-        let dependencies = dependencies.clone();
         let dependency_handles: Vec<SharedFuture> = dependencies
             .iter()
             .map(|target_name| self.make_target(target_name, depth + 1))
             .collect();
-        let makefile_path_str_owned = self.makefile_path_str.to_owned();
+        let normal_dependencies = self
+            .target_graph
+            .edges
+            .get(target_name)
+            .cloned()
+            .unwrap_or_default();
+        let make_args = self.make_args.clone();
+        let make_directory = self.make_directory.clone();
+        let up_to_date = self.up_to_date_targets.contains(target_name);
+        let dry_run = self.dry_run;
+        let keep_going = self.keep_going;
+        let failures = self.failures.clone();
+        let cancellation = self.cancellation.clone();
+        let job_slots = self.job_slots.clone();
+        let priority = self
+            .priorities
+            .get(target_name)
+            .copied()
+            .unwrap_or_default();
+        let run_logs = self.run_logs.clone();
+        let junit_report = self.junit_report.clone();
+        let build_trace = self.build_trace.clone();
+        let target_timings = self.target_timings.clone();
         let target_name_owned = target_name.clone();
-        let multi_progress_owned = self.multi_progress.clone();
+        let target_renderer: Arc<dyn TargetRenderer> =
+            self.renderer.add_target(target_name, depth).into();
 
-        let progress_bar = ProgressBar::new(2);
-        let progress_bar = multi_progress_owned.insert_from_back(0, progress_bar);
-        progress_bar.set_style(
-            ProgressStyle::with_template("     ⋯    {prefix}")
-                .expect("Could not construct progress bar template."),
-        );
-        let progress_bar = progress_bar.with_finish(ProgressFinish::AndLeave);
-        let indentation = match depth {
-            0 => "🎯".to_owned(),
-            depth => format!("{}{} ", "  ".repeat(depth), "↙"),
-        };
-        progress_bar.set_prefix(format!("{}{}", indentation, target_name_owned));
-        progress_bar.set_position(0);
+        let queued_time = Instant::now();
         let join_handle = task::spawn(async move {
-            join_all(dependency_handles).await;
-
-            progress_bar.reset_elapsed();
-            progress_bar.set_position(1);
-            progress_bar.set_style(
-                ProgressStyle::with_template(
-                    "{elapsed:>06} {spinner}  {prefix:40} 🛠️ | {wide_msg}",
-                )
-                .expect("Could not construct progress bar."),
-            );
-            progress_bar.enable_steady_tick(Duration::from_millis(16));
+            let record = |status: TargetStatus, duration: Duration, output_lines: &[OutputLine]| {
+                if let Some(junit_report) = &junit_report {
+                    junit_report.record(&target_name_owned, status, duration, output_lines);
+                }
+                status
+            };
+            let finish_cancelled = |duration: Duration, output_lines: &[OutputLine]| {
+                target_renderer.finish_cancelled();
+                record(TargetStatus::Cancelled, duration, output_lines)
+            };
+
+            let dependency_statuses = join_all(dependency_handles).await;
+            if let Some(build_trace) = &build_trace {
+                if !dependencies.is_empty() {
+                    build_trace.dependency_wait(&target_name_owned, queued_time, Instant::now());
+                }
+            }
+            if cancellation.is_cancelled() {
+                return finish_cancelled(Duration::ZERO, &[]);
+            }
+            if !dependency_statuses.iter().all(TargetStatus::is_success) {
+                target_renderer.finish_skipped();
+                return record(TargetStatus::Skipped, Duration::ZERO, &[]);
+            }
+
+            if up_to_date {
+                target_renderer.finish_up_to_date();
+                return record(TargetStatus::UpToDate, Duration::ZERO, &[]);
+            }
+
+            target_renderer.waiting_for_job();
+            let job_wait_start_time = Instant::now();
+            let job_slot = job_slots.acquire(priority).await;
+            if let Some(build_trace) = &build_trace {
+                build_trace.job_wait(&target_name_owned, job_wait_start_time, Instant::now());
+            }
+            if cancellation.is_cancelled() {
+                return finish_cancelled(Duration::ZERO, &[]);
+            }
+            target_renderer.started();
+            let target_start_time = Instant::now();
+
+            let target_log = run_logs
+                .as_ref()
+                .and_then(|run_logs| run_logs.target_log(&target_name_owned))
+                .map(Arc::new);
 
+            let output_sinks = OutputSinks {
+                target_renderer: target_renderer.clone(),
+                target_log: target_log.clone(),
+            };
+            // Checked now that the dependencies have been rebuilt.
+            let changed_dependencies =
+                newer_dependencies(&make_directory, &target_name_owned, &normal_dependencies);
             let result = make_individual_target(
                 dependencies,
-                &makefile_path_str_owned,
+                changed_dependencies,
+                &make_args,
                 &target_name_owned,
-                &progress_bar,
+                dry_run,
+                &cancellation,
+                output_sinks,
             )
             .await;
+            drop(job_slot);
+            if let Some(target_log) = &target_log {
+                target_log.flush();
+            }
+            let duration = Instant::now() - target_start_time;
 
-            progress_bar.set_position(2);
-            match result {
-                IndividualTargetResult::Success() => {
-                    progress_bar.set_style(
-                        ProgressStyle::with_template("{elapsed:>06} ✅ {prefix}")
-                            .expect("Could not construct progress bar template."),
-                    );
-                    progress_bar.finish();
+            let status = match result {
+                IndividualTargetResult::Success(output_lines) => {
+                    let output_lines: Vec<OutputLine> = output_lines.into_iter().collect();
+                    let dry_run_commands = dry_run.then(|| {
+                        output_lines
+                            .iter()
+                            .filter_map(|output_line| match output_line {
+                                OutputLine::Stdout(line) if !line.trim().is_empty() => {
+                                    Some(line.clone())
+                                }
+                                _ => None,
+                            })
+                            .collect()
+                    });
+                    target_renderer.finish_succeeded(dry_run_commands);
+                    record(TargetStatus::Succeeded, duration, &output_lines)
                 }
                 IndividualTargetResult::Failure(output_lines) => {
-                    progress_bar.set_style(
-                        ProgressStyle::with_template("{elapsed:>06} ❌ {prefix}")
-                            .expect("Could not construct progress bar template."),
-                    );
-
-                    println!("❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌");
-                    println!("❌");
-                    println!("❌ Target failed:");
-                    println!("❌");
-                    println!("❌     {}", target_name_owned);
-                    println!("❌");
-                    println!("❌ ⬇ See below for output. ⬇");
-                    println!("❌");
-                    println!("❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌");
-
-                    for output_line in output_lines {
-                        match output_line {
-                            OutputLine::Stdout(line) => println!("{}", line),
-                            OutputLine::Stderr(line) => eprintln!("{}", line),
+                    let output_lines: Vec<OutputLine> = output_lines.into_iter().collect();
+                    if cancellation.is_cancelled() {
+                        finish_cancelled(duration, &output_lines)
+                    } else {
+                        let log_path = target_log.map(|target_log| target_log.path.clone());
+                        target_renderer.finish_failed(log_path.as_deref());
+                        if !keep_going {
+                            // Stop everything else, so that the failure can be reported as soon as possible.
+                            cancellation.cancel(None);
                         }
+                        record(TargetStatus::Failed, duration, &output_lines);
+                        failures
+                            .lock()
+                            .expect("Could not access target failures.")
+                            .push(TargetFailure {
+                                target_name: target_name_owned.clone(),
+                                output_lines,
+                                log_path,
+                            });
+                        TargetStatus::Failed
                     }
-
-                    println!("❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌");
-                    println!("❌");
-                    println!("❌ ⬆  See above for output. ⬆");
-                    println!("❌");
-                    println!("❌ Target failed:");
-                    println!("❌");
-                    println!("❌     {}", target_name_owned);
-                    println!("❌");
-                    println!("❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌");
-
-                    exit(1)
                 }
+            };
+            target_timings.record(&target_name_owned, duration);
+            if let Some(build_trace) = &build_trace {
+                build_trace.recipe(
+                    &target_name_owned,
+                    target_start_time,
+                    target_start_time + duration,
+                    status,
+                );
             }
+            status
         });
         let join_handle = join_handle.shared();
         self.futures
@@ -271,99 +791,324 @@ impl SharedMake {
     }
 }
 
+fn print_failure(failure: &TargetFailure) {
+    println!("❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌");
+    println!("❌");
+    println!("❌ Target failed:");
+    println!("❌");
+    println!("❌     {}", failure.target_name);
+    println!("❌");
+    println!("❌ ⬇ See below for output. ⬇");
+    println!("❌");
+    println!("❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌");
+
+    for output_line in &failure.output_lines {
+        match output_line {
+            OutputLine::Stdout(line) => println!("{}", line),
+            OutputLine::Stderr(line) => eprintln!("{}", line),
+        }
+    }
+
+    println!("❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌");
+    println!("❌");
+    println!("❌ ⬆  See above for output. ⬆");
+    println!("❌");
+    if let Some(log_path) = &failure.log_path {
+        println!("❌ Log file:");
+        println!("❌");
+        println!("❌     {}", log_path.display());
+        println!("❌");
+    }
+    println!("❌ Target failed:");
+    println!("❌");
+    println!("❌     {}", failure.target_name);
+    println!("❌");
+    println!("❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌❌");
+}
+
 enum OutputLine {
     Stdout(String),
     Stderr(String),
 }
 
 enum IndividualTargetResult {
-    Success(),
+    Success(mpsc::Receiver<OutputLine>),
     #[allow(clippy::type_complexity)]
     Failure(mpsc::Receiver<OutputLine>),
 }
 
+/// `changed_dependencies` are the ones that should make the target out of date.
 async fn make_individual_target(
     dependencies: Vec<TargetName>,
-    makefile_path_str: &Option<String>,
+    changed_dependencies: Vec<TargetName>,
+    make_args: &[String],
     target_name: &TargetName,
-    progress_bar: &ProgressBar,
+    dry_run: bool,
+    cancellation: &Cancellation,
+    output_sinks: OutputSinks,
 ) -> IndividualTargetResult {
-    let mut args = make_args(makefile_path_str);
+    let mut args = make_args.to_vec();
+    if dry_run {
+        // Print the recipe instead of running it. Dependencies are marked as
+        // old using `-o` below, so this only ever covers the current target.
+        // `--silent` leaves out messages like "Nothing to be done for 'x'.",
+        // which would otherwise be shown as commands.
+        args.push("--dry-run".to_owned());
+        args.push("--silent".to_owned());
+    }
     args.push(target_name.0.clone());
 
     for dependency in &dependencies {
         args.push("-o".to_owned());
         args.push(dependency.0.clone());
     }
+    // `-o` also stops `make` from remaking the target because of a changed
+    // dependency, so those are marked as new as well.
+    for dependency in &changed_dependencies {
+        args.push("-W".to_owned());
+        args.push(dependency.0.clone());
+    }
     args.push("--".to_owned());
 
     let mut child = Command::new("make")
         .args(args)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
+        // In its own process group, reading from the terminal would stop `make` (with `SIGTTIN`).
+        .stdin(Stdio::null())
+        .process_group(0)
         .spawn()
         .expect("failed to execute process");
+    let process_group = child.id();
+    cancellation.register(process_group);
 
     let (sender, receiver) = mpsc::channel::<OutputLine>();
 
-    // TODO: deduplicate stderr and stdout implementations.
-    let sender_clone = sender.clone();
-    let stdout_reader = BufReader::new(
+    // Reading output and waiting for the child both block, so they run on
+    // blocking threads rather than the executor (which may only have a single
+    // thread), to allow several targets to run at the same time.
+    let stdout_join_handle = spawn_output_reader(
         child
             .stdout
             .take()
             .expect("Could not get stdout for a `make` invocation."),
+        OutputLine::Stdout,
+        output_sinks.clone(),
+        sender.clone(),
     );
-    let stdout_progress_bar_clone: ProgressBar = progress_bar.clone();
-    let stdout_join_handle = task::spawn(async move {
-        stdout_reader
-            .lines()
-            .map_while(Result::ok)
-            .for_each(move |line| {
-                if !line.trim().is_empty() {
-                    stdout_progress_bar_clone.set_message(line.clone())
-                };
-                // Ignore `send` failures, since those could be due to closing down the program from a target failure somewhere else.
-                let _ = sender_clone.send(OutputLine::Stdout(line));
-            });
-    });
-
-    let stderr_reader = BufReader::new(
+    let stderr_join_handle = spawn_output_reader(
         child
             .stderr
             .take()
-            .expect("Could not get stdout for a `make` invocation."),
+            .expect("Could not get stderr for a `make` invocation."),
+        OutputLine::Stderr,
+        output_sinks,
+        sender,
     );
-    let stderr_progress_bar_clone: ProgressBar = progress_bar.clone();
-    let stderr_join_handle = task::spawn(async move {
-        stderr_reader
+    let success = task::spawn_blocking(move || {
+        child
+            .wait()
+            .expect("Error while waiting for a `make` invocation to finish")
+            .success()
+    })
+    .await;
+    cancellation.unregister(process_group);
+    join_all([stdout_join_handle, stderr_join_handle]).await;
+    if success {
+        IndividualTargetResult::Success(receiver)
+    } else {
+        IndividualTargetResult::Failure(receiver)
+    }
+}
+
+/// Everywhere that output lines go while a target is running (in addition to
+/// being collected for the failure report).
+#[derive(Clone)]
+struct OutputSinks {
+    target_renderer: Arc<dyn TargetRenderer>,
+    target_log: Option<Arc<TargetLog>>,
+}
+
+impl OutputSinks {
+    fn receive(&self, output_line: &OutputLine) {
+        self.target_renderer.output_line(output_line);
+        if let Some(target_log) = &self.target_log {
+            target_log.write(output_line);
+        }
+    }
+}
+
+fn spawn_output_reader(
+    reader: impl Read + Send + 'static,
+    to_output_line: fn(String) -> OutputLine,
+    output_sinks: OutputSinks,
+    sender: mpsc::Sender<OutputLine>,
+) -> JoinHandle<()> {
+    task::spawn_blocking(move || {
+        BufReader::new(reader)
             .lines()
             .map_while(Result::ok)
             .for_each(move |line| {
-                if !line.trim().is_empty() {
-                    stderr_progress_bar_clone.set_message(line.clone())
-                };
+                let output_line = to_output_line(line);
+                output_sinks.receive(&output_line);
                 // Ignore `send` failures, since those could be due to closing down the program from a target failure somewhere else.
-                let _ = sender.send(OutputLine::Stderr(line));
+                let _ = sender.send(output_line);
             })
-    });
-    if child
-        .wait()
-        .expect("Error while waiting for a `make` invocation to finish")
-        .success()
-    {
-        IndividualTargetResult::Success()
-    } else {
-        join_all([stdout_join_handle, stderr_join_handle]).await;
-        IndividualTargetResult::Failure(receiver)
-    }
+    })
 }
 
-fn make_args(makefile_path_str: &Option<String>) -> Vec<String> {
+/// Arguments for every `make` invocation, so that they all see the same rules and variables.
+fn make_args(makefile_path_str: &Option<String>, options: &MakArgs) -> Vec<String> {
     let mut args = vec![];
     if let Some(makefile_path_str) = makefile_path_str {
         args.push("-f".to_owned());
         args.push(makefile_path_str.to_owned());
     };
+    if options.make_directory().is_some() {
+        // Every target is built in the same directory, so this would only be noise.
+        args.push("--no-print-directory".to_owned());
+    }
+    args.extend(options.make_args.iter().cloned());
+    args.extend(options.variables.iter().cloned());
     args
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{create_dir_all, read_to_string, remove_dir_all, write, File},
+        path::Path,
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use async_std::task::block_on;
+
+    use crate::{
+        cancel::Cancellation,
+        parse::TargetName,
+        render::{HiddenRenderer, Renderer},
+    };
+
+    use super::{
+        make_individual_target, newer_dependencies, IndividualTargetResult, OutputLine, OutputSinks,
+    };
+
+    #[test]
+    fn test_rebuild_after_dependency_changed() {
+        let directory =
+            std::env::temp_dir().join(format!("mak-test-rebuild-{}", std::process::id()));
+        create_dir_all(&directory).unwrap();
+        let path = |file_name: &str| directory.join(file_name).to_string_lossy().into_owned();
+        write(
+            path("Makefile"),
+            format!(
+                "{lib_o}: {lib_c}\n\tcp {lib_c} {lib_o}\n",
+                lib_o = path("lib.o"),
+                lib_c = path("lib.c")
+            ),
+        )
+        .unwrap();
+        let make_args = vec!["-f".to_owned(), path("Makefile")];
+        let make_lib_o = || {
+            let target_name = TargetName(path("lib.o"));
+            let output_sinks = OutputSinks {
+                target_renderer: Arc::from(
+                    HiddenRenderer {
+                        stream_output: false,
+                    }
+                    .add_target(&target_name, 0),
+                ),
+                target_log: None,
+            };
+            let dependencies = vec![TargetName(path("lib.c"))];
+            let changed_dependencies =
+                newer_dependencies(Path::new(""), &target_name, &dependencies);
+            let result = block_on(make_individual_target(
+                dependencies,
+                changed_dependencies,
+                &make_args,
+                &target_name,
+                false,
+                &Cancellation::default(),
+                output_sinks,
+            ));
+            assert!(matches!(result, IndividualTargetResult::Success(_)));
+        };
+
+        write(path("lib.c"), "1").unwrap();
+        make_lib_o();
+        assert_eq!(read_to_string(path("lib.o")).unwrap(), "1");
+
+        // The dependency changes after the target was built (e.g. while watching).
+        write(path("lib.c"), "2").unwrap();
+        File::options()
+            .write(true)
+            .open(path("lib.c"))
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        make_lib_o();
+        assert_eq!(read_to_string(path("lib.o")).unwrap(), "2");
+
+        remove_dir_all(&directory).unwrap();
+    }
+
+    fn make_target_without_dependencies(
+        makefile_source: &str,
+        target_name: &str,
+        dry_run: bool,
+    ) -> Vec<OutputLine> {
+        let directory =
+            std::env::temp_dir().join(format!("mak-test-{}-{}", target_name, std::process::id()));
+        create_dir_all(&directory).unwrap();
+        let makefile_path = directory.join("Makefile").to_string_lossy().into_owned();
+        write(&makefile_path, makefile_source).unwrap();
+        let target_name = TargetName(target_name.to_owned());
+        let output_sinks = OutputSinks {
+            target_renderer: Arc::from(
+                HiddenRenderer {
+                    stream_output: false,
+                }
+                .add_target(&target_name, 0),
+            ),
+            target_log: None,
+        };
+        let result = block_on(make_individual_target(
+            vec![],
+            vec![],
+            &["-f".to_owned(), makefile_path],
+            &target_name,
+            dry_run,
+            &Cancellation::default(),
+            output_sinks,
+        ));
+        remove_dir_all(&directory).unwrap();
+        let IndividualTargetResult::Success(output_lines) = result else {
+            panic!("Could not make {}", target_name);
+        };
+        output_lines.into_iter().collect()
+    }
+
+    #[test]
+    fn test_recipe_reading_stdin() {
+        let output_lines = make_target_without_dependencies("read:\n\t@cat\n", "read", false);
+        assert!(output_lines.is_empty());
+    }
+
+    #[test]
+    fn test_dry_run_output() {
+        let output_lines = make_target_without_dependencies(
+            "nothing:\ncommands: nothing\n\techo hi\n",
+            "nothing",
+            true,
+        );
+        assert!(output_lines.is_empty());
+        let output_lines = make_target_without_dependencies(
+            "nothing:\ncommands: nothing\n\techo hi\n",
+            "commands",
+            true,
+        );
+        assert!(matches!(&output_lines[..], [OutputLine::Stdout(line)] if line == "echo hi"));
+    }
+}
@@ -1,10 +1,11 @@
-use clap::{CommandFactory, Parser};
-use clap_complete::generator::generate;
-use clap_complete::{Generator, Shell};
-use std::io::stdout;
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use clap_complete::Shell;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::process::exit;
 
+use crate::completions::print_completions;
+
 /// Fast make
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -14,67 +15,211 @@ pub(crate) struct MakArgs {
     #[clap(short = 'f', long = "file", alias = "makefile", verbatim_doc_comment)]
     pub(crate) makefile_path: Option<PathBuf>,
 
-    /// Makefile target
+    /// Makefile targets, and variable overrides like `VAR=value` (which are passed to every `make` invocation).
     #[clap(verbatim_doc_comment)]
     pub(crate) targets: Vec<String>, // TODO: `Vec<TargetName>`
 
+    /// Variable overrides from the positional arguments.
+    #[clap(skip)]
+    pub(crate) variables: Vec<String>,
+
+    /// Pass an extra argument to every `make` invocation (can be repeated), e.g. `--make-arg=-e` or `--make-arg=-C --make-arg=dir`.
+    #[clap(
+        long = "make-arg",
+        allow_hyphen_values = true,
+        verbatim_doc_comment,
+        id = "ARG"
+    )]
+    pub(crate) make_args: Vec<String>,
+
+    /// Maximum number of targets to build at the same time.
+    /// Defaults to the number of available CPUs.
+    #[clap(short = 'j', long, verbatim_doc_comment)]
+    pub(crate) jobs: Option<NonZeroUsize>,
+
+    /// Keep building targets that do not depend on a failed target, and report all failures at the end.
+    #[clap(short = 'k', long, verbatim_doc_comment)]
+    pub(crate) keep_going: bool,
+
+    /// Print the output of every target as it is produced (prefixed with the target name), instead of only printing the output of failed targets at the end.
+    #[clap(long, verbatim_doc_comment)]
+    pub(crate) stream_output: bool,
+
+    /// How to report progress and results.
+    /// `json` prints one JSON object per line for each build event (to stdout), instead of showing progress bars.
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text, verbatim_doc_comment)]
+    pub(crate) output_format: OutputFormat,
+
+    /// How to show the progress of each target (ignored for `--output-format json`).
+    /// `auto` uses `fancy` progress bars if stderr (where they are drawn) is a terminal, and otherwise `plain` lines (e.g. for CI logs).
+    #[clap(long, value_enum, default_value_t = Progress::Auto, verbatim_doc_comment)]
+    pub(crate) progress: Progress,
+
+    /// Print a summary of where the time went at the end of the build: the critical path, the slowest targets, and the achieved parallelism.
+    #[clap(long, verbatim_doc_comment)]
+    pub(crate) timings: bool,
+
+    /// Write a JUnit XML report to the given path, with a test case for each target (including its output).
+    #[clap(long, verbatim_doc_comment, id = "PATH")]
+    pub(crate) junit: Option<PathBuf>,
+
+    /// Write a timeline of the build to the given path, in the Chrome Trace Event format.
+    /// This can be opened using `chrome://tracing` or https://ui.perfetto.dev/ to see parallelism and bottlenecks.
+    #[clap(long, verbatim_doc_comment, id = "FILE")]
+    pub(crate) trace: Option<PathBuf>,
+
+    /// Keep running, and rebuild whatever is affected when a source file of the specified targets changes.
+    /// Source files are the prerequisites that exist and have no recipe.
+    #[clap(long, verbatim_doc_comment)]
+    pub(crate) watch: bool,
+
+    /// Pick the target to build interactively (instead of building the default target).
+    /// This also happens automatically in a terminal when no target is specified and there is no default target.
+    #[clap(long, verbatim_doc_comment)]
+    pub(crate) pick: bool,
+
     /// Show how commands would have been run, without actually running.
     #[clap(long, group = "command-like", verbatim_doc_comment)]
     pub(crate) dry_run: bool,
 
-    /// Print the dependency graph as JSON (instead of running anything).
+    /// Print the dependency graph (instead of running anything).
+    /// If targets are specified, only they and their (transitive) dependencies are included.
     #[clap(long, group = "command-like", verbatim_doc_comment)]
     pub(crate) print_graph: bool,
 
+    /// Format for `--print-graph`.
+    /// `dot` is for Graphviz, and `mermaid` is a flowchart that can be embedded in Markdown.
+    #[clap(long, value_enum, default_value_t = GraphFormat::Json, requires = "print_graph", verbatim_doc_comment)]
+    pub(crate) graph_format: GraphFormat,
+
+    /// Only include dependencies up to this many levels below the specified targets, for `--print-graph`.
+    #[clap(long, requires = "print_graph", verbatim_doc_comment, id = "DEPTH")]
+    pub(crate) graph_depth: Option<usize>,
+
+    /// Leave out phony targets and files that are not targets, for `--print-graph`.
+    /// Their dependencies are connected directly to the targets that depend on them.
+    #[clap(long, requires = "print_graph", verbatim_doc_comment)]
+    pub(crate) hide_phony: bool,
+
+    /// Print targets related to the specified targets (or the default target), one per line (instead of running anything).
+    /// `dependencies` lists everything they (transitively) depend on, `dependents` lists everything that would be rebuilt if they changed, and `build-order` lists them and their dependencies in an order that they can be built.
+    /// Use `--output-format json` to print a JSON array instead.
+    #[clap(long, value_enum, group = "command-like", verbatim_doc_comment)]
+    pub(crate) query: Option<GraphQuery>,
+
+    /// Print the targets with their descriptions, grouped by section (instead of running anything).
+    /// Descriptions are read from `## …` comments after the prerequisites of a rule, or from the comment lines just before it. Sections start with a `##@ …` comment.
+    /// Use `--output-format json` to print a JSON array instead.
+    #[clap(long, group = "command-like", verbatim_doc_comment)]
+    pub(crate) list: bool,
+
     /// Print the the list of targets, one per line (instead of running anything).
+    /// Targets with a description (see `--list`) are followed by a tab and the description.
     /// Does not return an error when `Makefile` is missing, to avoid unexpected issues with shell completions.
     #[clap(long, group = "command-like", verbatim_doc_comment)]
     pub(crate) print_completion_targets: bool,
 
+    /// Print the log of the most recent run of the given target (instead of running anything).
+    /// Logs of the last few runs are kept under `.mak/logs`.
+    #[clap(long, group = "command-like", verbatim_doc_comment, id = "TARGET")]
+    pub(crate) show_log: Option<String>,
+
     /// Print completions for the given shell (instead of running anything).
     /// These can be loaded/stored permanently (e.g. when using Homebrew), but they can also be sourced directly, e.g.:
     ///
     ///  mak --completions fish | source # fish
     ///  source <(mak --completions zsh) # zsh
+    ///  source <(mak --completions bash) # bash
+    ///
+    /// Targets are completed for the Makefile specified using `--file` (if any).
     #[clap(long, group = "command-like", verbatim_doc_comment, id = "SHELL")]
     pub(crate) completions: Option<Shell>,
 }
 
-fn completions_for_shell(cmd: &mut clap::Command, generator: impl Generator) {
-    generate(generator, cmd, "mak", &mut stdout());
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum GraphFormat {
+    Json,
+    Dot,
+    Mermaid,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum GraphQuery {
+    Dependencies,
+    Dependents,
+    BuildOrder,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Progress {
+    Auto,
+    Fancy,
+    Plain,
+    None,
 }
 
 pub(crate) fn get_options() -> MakArgs {
     let mut command = MakArgs::command();
 
-    let args = MakArgs::parse();
+    let mut args = MakArgs::parse();
     if let Some(shell) = args.completions {
-        completions_for_shell(&mut command, shell);
-        // TODO: other shells?
-        if shell == Shell::Fish {
-            // Complete targets for `fish` similarly to https://github.com/fish-shell/fish-shell/blob/3ce67ecbd2348fbe13e86a00bea6ce998710729a/share/completions/make.fish
-            println!("
-function __fish_complete_mak_targets
-    # TODO: handle `-f=`?
-    set -l file (string replace -rf '^mak .*((-f|--file)(=| +))([^ ]*) .*$' '$4' -- $argv)
-    if test -n \"$file\"
-        mak --file \"$file\" --print-completion-targets
-    else
-        mak --print-completion-targets
-    end
-end
-complete -c mak -n 'commandline -ct | string match -q \"*=*\"' -a \"(__fish_complete_mak_targets (commandline -p))\" -d Target
-complete -f -c mak -n 'commandline -ct | not string match -q \"*=*\"' -a \"(__fish_complete_mak_targets (commandline -p))\" -d Target
-");
-        }
+        print_completions(&mut command, shell);
         exit(0);
     }
 
+    args.split_variables();
+    if args.pick && !args.targets.is_empty() {
+        command
+            .error(
+                ErrorKind::ArgumentConflict,
+                "the argument '--pick' cannot be used with targets",
+            )
+            .exit();
+    }
+
     args
 }
 
+impl MakArgs {
+    /// Like `make`, treats any positional argument containing `=` as a variable override.
+    fn split_variables(&mut self) {
+        (self.variables, self.targets) = std::mem::take(&mut self.targets)
+            .into_iter()
+            .partition(|target| target.contains('='));
+    }
+
+    /// The directory that `make` runs in, if changed using `--make-arg`s like `-C dir` or `--directory=dir`.
+    pub(crate) fn make_directory(&self) -> Option<PathBuf> {
+        let mut directory: Option<PathBuf> = None;
+        let mut make_args = self.make_args.iter();
+        while let Some(make_arg) = make_args.next() {
+            let path = match make_arg.as_str() {
+                "-C" | "--directory" => make_args.next().map(|s| s.as_str()),
+                _ => make_arg
+                    .strip_prefix("--directory=")
+                    .or_else(|| make_arg.strip_prefix("-C")),
+            };
+            if let Some(path) = path {
+                // Like `make`, multiple directories are relative to each other.
+                directory = Some(directory.unwrap_or_default().join(path));
+            }
+        }
+        directory
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+
+    use clap::Parser;
+
     use crate::options::MakArgs;
 
     // https://docs.rs/clap/latest/clap/_derive/_tutorial/index.html#testing
@@ -84,4 +229,49 @@ mod tests {
 
         MakArgs::command().debug_assert();
     }
+
+    #[test]
+    fn test_make_directory() {
+        let make_directory = |args: &[&str]| {
+            MakArgs::try_parse_from(["mak"].iter().chain(args))
+                .unwrap()
+                .make_directory()
+        };
+        assert_eq!(make_directory(&[]), None);
+        assert_eq!(make_directory(&["--make-arg=-k"]), None);
+        assert_eq!(
+            make_directory(&["--make-arg", "-C", "--make-arg", "dir"]),
+            Some(PathBuf::from("dir"))
+        );
+        assert_eq!(
+            make_directory(&["--make-arg=-Cdir"]),
+            Some(PathBuf::from("dir"))
+        );
+        assert_eq!(
+            make_directory(&["--make-arg=--directory=dir"]),
+            Some(PathBuf::from("dir"))
+        );
+        assert_eq!(
+            make_directory(&["--make-arg=--directory", "--make-arg=dir"]),
+            Some(PathBuf::from("dir"))
+        );
+        // Later directories are relative to earlier ones, unless absolute.
+        assert_eq!(
+            make_directory(&["--make-arg=-C", "--make-arg=a", "--make-arg=-Cb"]),
+            Some(PathBuf::from("a/b"))
+        );
+        assert_eq!(
+            make_directory(&["--make-arg=-Ca", "--make-arg=-C/b"]),
+            Some(PathBuf::from("/b"))
+        );
+    }
+
+    #[test]
+    fn test_split_variables() {
+        let mut args =
+            MakArgs::try_parse_from(["mak", "build", "CC=clang", "test", "FLAGS=-O2 -g"]).unwrap();
+        args.split_variables();
+        assert_eq!(args.targets, vec!["build", "test"]);
+        assert_eq!(args.variables, vec!["CC=clang", "FLAGS=-O2 -g"]);
+    }
 }
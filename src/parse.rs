@@ -4,8 +4,9 @@ use indexmap::IndexMap;
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_till, take_until, take_while, take_while1},
-    combinator::{all_consuming, not, opt},
-    multi::{many0, separated_list0},
+    combinator::{not, opt, verify},
+    error::{Error, ErrorKind},
+    multi::{many0, separated_list0, separated_list1},
     IResult,
 };
 
@@ -19,12 +20,137 @@ impl Display for TargetName {
     }
 }
 
+/// Information about a target from the `make` database, beyond its prerequisites.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct TargetMetadata {
+    /// Preceded by `# Not a target:`, i.e. a file without a rule (e.g. a source file).
+    pub(crate) not_a_target: bool,
+    /// A prerequisite of `.PHONY`.
+    pub(crate) phony: bool,
+    /// A prerequisite of `.PRECIOUS`.
+    pub(crate) precious: bool,
+    /// An intermediate file (e.g. a prerequisite of `.INTERMEDIATE`).
+    pub(crate) intermediate: bool,
+    /// A prerequisite of `.SECONDARY`.
+    pub(crate) secondary: bool,
+    /// `File has been updated.`, i.e. `make` already updated the file during this run (while building the database).
+    pub(crate) updated: bool,
+    /// `Implicit rule search has been done.` If it hasn't, a file that is "not a
+    /// target" may still be buildable using a pattern rule.
+    pub(crate) implicit_rule_search_done: bool,
+    /// Modification time as printed by `make`, if it was checked and the file exists.
+    pub(crate) last_modified: Option<String>,
+    /// Where the recipe was defined, if the target has one.
+    pub(crate) recipe_location: Option<RecipeLocation>,
+    /// Recipe lines, without the leading tab and with variables unexpanded.
+    pub(crate) recipe: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct RecipeLocation {
+    pub(crate) file: String,
+    pub(crate) line: usize,
+}
+
+/// A user-defined pattern rule. The built-in ones are disabled by `mak`'s use of `make -r`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct PatternRule {
+    /// e.g. `["%.o"]`
+    pub(crate) target_patterns: Vec<String>,
+    /// e.g. `["%.c"]`
+    pub(crate) prerequisites: Vec<String>,
+    pub(crate) order_only_prerequisites: Vec<String>,
+    pub(crate) recipe_location: Option<RecipeLocation>,
+    pub(crate) recipe: Vec<String>,
+}
+
+impl TargetMetadata {
+    fn from_lines(not_a_target: bool, lines: Vec<TargetLine>) -> Self {
+        let mut target_metadata = TargetMetadata {
+            not_a_target,
+            ..Default::default()
+        };
+        for line in lines {
+            let comment = match line {
+                TargetLine::Comment(comment) => comment.trim(),
+                TargetLine::Recipe(recipe_line) => {
+                    target_metadata.recipe.push(recipe_line.to_owned());
+                    continue;
+                }
+            };
+            match comment {
+                "Phony target (prerequisite of .PHONY)." => target_metadata.phony = true,
+                "Precious file (prerequisite of .PRECIOUS)." => target_metadata.precious = true,
+                "File is an intermediate prerequisite." => target_metadata.intermediate = true,
+                "File is secondary (prerequisite of .SECONDARY)." => {
+                    target_metadata.secondary = true
+                }
+                "File has been updated." => target_metadata.updated = true,
+                "Implicit rule search has been done." => {
+                    target_metadata.implicit_rule_search_done = true
+                }
+                _ => {
+                    if let Some(last_modified) = comment.strip_prefix("Last modified ") {
+                        target_metadata.last_modified = Some(last_modified.to_owned());
+                    } else if let Some(recipe_location) = parse_recipe_location(comment) {
+                        target_metadata.recipe_location = Some(recipe_location);
+                    }
+                }
+            }
+        }
+        target_metadata
+    }
+}
+
+/// Parses e.g. `recipe to execute (from 'Makefile', line 12):`
+fn parse_recipe_location(comment: &str) -> Option<RecipeLocation> {
+    let location = comment
+        .strip_prefix("recipe to execute (from '")?
+        .strip_suffix("):")?;
+    let (file, line) = location.rsplit_once("', line ")?;
+    Some(RecipeLocation {
+        file: file.to_owned(),
+        line: line.parse().ok()?,
+    })
+}
+
 #[derive(Debug, Default, Serialize)]
 pub(crate) struct TargetGraph {
+    /// Normal prerequisites, for every target.
     pub(crate) edges: IndexMap<TargetName, Vec<TargetName>>,
+    /// Order-only prerequisites (listed after a `|`), only for targets that have any.
+    pub(crate) order_only_edges: IndexMap<TargetName, Vec<TargetName>>,
+    pub(crate) metadata: IndexMap<TargetName, TargetMetadata>,
+    /// Rules like `%.o: %.c`, from the implicit rules section of the database.
+    pub(crate) pattern_rules: Vec<PatternRule>,
     pub(crate) default_goal: Option<TargetName>,
 }
 
+impl TargetGraph {
+    /// Removes all information about targets that don't satisfy the predicate.
+    pub(crate) fn retain_targets(&mut self, predicate: impl Fn(&TargetName) -> bool) {
+        self.edges.retain(|target_name, _| predicate(target_name));
+        self.order_only_edges
+            .retain(|target_name, _| predicate(target_name));
+        self.metadata
+            .retain(|target_name, _| predicate(target_name));
+    }
+
+    /// Both normal and order-only prerequisites of a target, i.e. everything
+    /// that has to be built before it.
+    pub(crate) fn all_dependencies(&self, target_name: &TargetName) -> Option<Vec<TargetName>> {
+        let dependencies = self.edges.get(target_name)?;
+        let order_only_dependencies = self.order_only_edges.get(target_name);
+        Some(
+            dependencies
+                .iter()
+                .chain(order_only_dependencies.into_iter().flatten())
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
 fn is_allowed_target_name_first_char(c: char) -> bool {
     !is_makefile_whitespace(c) && c != '\n' && c != '\r' && c != ':'
 }
@@ -64,14 +190,20 @@ fn take_until_newline(input: &str) -> IResult<&str, ()> {
 
 // Starts with optional whitespace
 fn parse_dependency(input: &str) -> IResult<&str, TargetName> {
-    let (input, _) = many0(alt((
-        tag(" "),
-        tag("\t"),
-        tag("|"),
-        tag("\\\n"),
-        tag("\\\r\n"),
-    )))(input)?;
-    parse_target_name(input)
+    let (input, _) = parse_dependency_whitespace(input)?;
+    // A lone `|` separates normal from order-only dependencies.
+    verify(parse_target_name, |target_name| target_name.0 != "|")(input)
+}
+
+fn parse_dependency_whitespace(input: &str) -> IResult<&str, ()> {
+    let (input, _) = many0(alt((tag(" "), tag("\t"), tag("\\\n"), tag("\\\r\n"))))(input)?;
+    Ok((input, ()))
+}
+
+fn parse_order_only_dependencies(input: &str) -> IResult<&str, Vec<TargetName>> {
+    let (input, _) = parse_dependency_whitespace(input)?;
+    let (input, _) = tag("|")(input)?;
+    many0(parse_dependency)(input)
 }
 
 fn target_name_with_colon(input: &str) -> IResult<&str, TargetName> {
@@ -80,25 +212,95 @@ fn target_name_with_colon(input: &str) -> IResult<&str, TargetName> {
     Ok((input, target_name))
 }
 
+fn parse_not_a_target(input: &str) -> IResult<&str, ()> {
+    let (input, _) = tag("# Not a target:\n")(input)?;
+    Ok((input, ()))
+}
+
+enum TargetLine<'a> {
+    /// e.g. `#  Phony target (prerequisite of .PHONY).`
+    Comment(&'a str),
+    /// e.g. `\techo "hello world"`
+    Recipe(&'a str),
+}
+
+/// A line directly following a target line, until the next blank line.
+fn parse_target_line(input: &str) -> IResult<&str, TargetLine<'_>> {
+    let (input, _) = tag("\n")(input)?;
+    let (input, is_comment) = alt((tag("#"), tag("\t")))(input)?;
+    let (input, text) = take_till(|c| c == '\n')(input)?;
+    let target_line = match is_comment {
+        "#" => TargetLine::Comment(text),
+        _ => TargetLine::Recipe(text),
+    };
+    Ok((input, target_line))
+}
+
 fn parse_makefile_target(input: &str) -> IResult<&str, Option<TargetGraph>> {
+    let (input, not_a_target) = opt(parse_not_a_target)(input)?;
     let (input, target_name) = target_name_with_colon(input)?;
     let mut target_graph = TargetGraph::default();
 
     let (input, dependencies) = many0(parse_dependency)(input)?;
+    let (input, order_only_dependencies) = opt(parse_order_only_dependencies)(input)?;
 
     let (input, _) = take_while(is_makefile_whitespace)(input)?;
     let (input, _) = parse_optional_comment(input)?;
+    let (input, target_lines) = many0(parse_target_line)(input)?;
 
+    let metadata = TargetMetadata::from_lines(not_a_target.is_some(), target_lines);
+    target_graph.metadata.insert(target_name.clone(), metadata);
+    if let Some(order_only_dependencies) = order_only_dependencies {
+        target_graph
+            .order_only_edges
+            .insert(target_name.clone(), order_only_dependencies);
+    }
     target_graph.edges.insert(target_name, dependencies);
     Ok((input, Some(target_graph)))
 }
 
+fn parse_pattern_name(input: &str) -> IResult<&str, String> {
+    let (input, target_name) =
+        verify(parse_target_name, |target_name| target_name.0.contains('%'))(input)?;
+    Ok((input, target_name.0))
+}
+
+fn parse_pattern_rule(input: &str) -> IResult<&str, Option<TargetGraph>> {
+    let (input, target_patterns) = separated_list1(tag(" "), parse_pattern_name)(input)?;
+    let (input, _) = tag(":")(input)?;
+
+    let (input, prerequisites) = many0(parse_dependency)(input)?;
+    let (input, order_only_prerequisites) = opt(parse_order_only_dependencies)(input)?;
+
+    let (input, _) = take_while(is_makefile_whitespace)(input)?;
+    let (input, _) = parse_optional_comment(input)?;
+    let (input, target_lines) = many0(parse_target_line)(input)?;
+
+    let metadata = TargetMetadata::from_lines(false, target_lines);
+    let pattern_rule = PatternRule {
+        target_patterns,
+        prerequisites: prerequisites.into_iter().map(|t| t.0).collect(),
+        order_only_prerequisites: order_only_prerequisites
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| t.0)
+            .collect(),
+        recipe_location: metadata.recipe_location,
+        recipe: metadata.recipe,
+    };
+    let target_graph = TargetGraph {
+        pattern_rules: vec![pattern_rule],
+        ..Default::default()
+    };
+    Ok((input, Some(target_graph)))
+}
+
 fn parse_default_goal(input: &str) -> IResult<&str, Option<TargetGraph>> {
     let (input, _) = tag(".DEFAULT_GOAL := ")(input)?;
     let (input, target_name) = parse_target_name(input)?;
     let target_graph = TargetGraph {
-        edges: IndexMap::new(),
         default_goal: Some(target_name),
+        ..Default::default()
     };
     Ok((input, Some(target_graph)))
 }
@@ -116,21 +318,41 @@ fn parse_ignored_line(input: &str) -> IResult<&str, Option<TargetGraph>> {
     Ok((input, None))
 }
 
+type LineParser = fn(&str) -> IResult<&str, Option<TargetGraph>>;
+
+/// Parsers for a single line (or group of lines) of the database, in priority order.
+const LINE_PARSERS: [(&str, LineParser); 5] = [
+    ("parse_two_line_define", parse_two_line_define), // Takes priority due to similar syntax
+    ("parse_pattern_rule", parse_pattern_rule),       // Takes priority over regular targets
+    ("parse_makefile_target", parse_makefile_target),
+    ("parse_default_goal", parse_default_goal),
+    ("parse_ignored_line", parse_ignored_line),
+];
+
+fn parse_line(input: &str) -> IResult<&str, Option<TargetGraph>> {
+    for (_, line_parser) in LINE_PARSERS {
+        match line_parser(input) {
+            Err(nom::Err::Error(_)) => continue,
+            result => return result,
+        }
+    }
+    Err(nom::Err::Error(Error::new(input, ErrorKind::Alt)))
+}
+
 fn parse_makefile(input: &str) -> IResult<&str, TargetGraph> {
     let mut main_target_graph = TargetGraph::default();
 
     // TODO: fail on something that looks like a target declaration without valid deps.
-    let (input, target_graphs) = separated_list0(
-        alt((tag("\n"), tag("\r\n"))),
-        alt((
-            parse_two_line_define, // Takes priority due to similar syntax
-            parse_makefile_target,
-            parse_default_goal,
-            parse_ignored_line,
-        )),
-    )(input)?;
+    let (input, target_graphs) = separated_list0(alt((tag("\n"), tag("\r\n"))), parse_line)(input)?;
     for target_graph in target_graphs.into_iter().flatten() {
         main_target_graph.edges.extend(target_graph.edges);
+        main_target_graph
+            .order_only_edges
+            .extend(target_graph.order_only_edges);
+        main_target_graph.metadata.extend(target_graph.metadata);
+        main_target_graph
+            .pattern_rules
+            .extend(target_graph.pattern_rules);
         if let Some(default_goal) = target_graph.default_goal {
             main_target_graph.default_goal = Some(default_goal); // TODO: test against multiple default goals?
         }
@@ -139,16 +361,196 @@ fn parse_makefile(input: &str) -> IResult<&str, TargetGraph> {
     Ok((input, main_target_graph))
 }
 
+/// A location in the `make` database that could not be parsed.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ParseError {
+    /// 1-indexed line number.
+    pub(crate) line: usize,
+    /// 1-indexed column (in characters).
+    pub(crate) column: usize,
+    /// The full text of the offending line.
+    pub(crate) line_text: String,
+    /// The line parser that got the furthest before failing.
+    pub(crate) parser: &'static str,
+    pub(crate) message: String,
+}
+
+impl ParseError {
+    /// Diagnoses why parsing stopped at `remaining` by re-running each line
+    /// parser from the start of that line, and blaming the one that got furthest.
+    fn new(input: &str, remaining: &str) -> Self {
+        let mut offset = input.len() - remaining.len();
+        for separator in ["\n", "\r\n"] {
+            if remaining.starts_with(separator) {
+                offset += separator.len();
+                break;
+            }
+        }
+        let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_input = &input[line_start..];
+
+        let (parser, progress, message) = LINE_PARSERS
+            .iter()
+            .map(|(name, line_parser)| {
+                let (rest, message) = match line_parser(line_input) {
+                    Ok((rest, _)) => (rest, "expected end of line".to_owned()),
+                    Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                        (e.input, format!("expected {}", e.code.description()))
+                    }
+                    Err(nom::Err::Incomplete(_)) => (line_input, "incomplete input".to_owned()),
+                };
+                (*name, line_input.len() - rest.len(), message)
+            })
+            .rev() // `max_by_key` returns the last maximum, but earlier parsers take priority.
+            .max_by_key(|(_, progress, _)| *progress)
+            .expect("No line parsers available.");
+
+        let position = line_start + progress;
+        let error_line_start = input[..position].rfind('\n').map_or(0, |i| i + 1);
+        let error_line_end = input[position..]
+            .find('\n')
+            .map_or(input.len(), |i| position + i);
+        ParseError {
+            line: input[..position].matches('\n').count() + 1,
+            column: input[error_line_start..position].chars().count() + 1,
+            line_text: input[error_line_start..error_line_end]
+                .trim_end_matches('\r')
+                .to_owned(),
+            parser,
+            message,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        writeln!(
+            f,
+            "Could not parse the `make` database at line {}, column {} ({} in `{}`):",
+            self.line, self.column, self.message, self.parser
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", line_number, self.line_text)?;
+        write!(f, "{} | {}^", gutter, " ".repeat(self.column - 1))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 impl TryFrom<&String> for TargetGraph {
-    type Error = String;
+    type Error = ParseError;
 
     fn try_from(value: &String) -> Result<Self, Self::Error> {
-        match all_consuming(parse_makefile)(value) {
-            Ok((_, target_graph)) => Ok(target_graph),
-            Err(e) => {
-                eprintln!("Makefile parsing error: {:#?}", e);
-                Err("Invalid Makefile".into()) // TODO: pass on error
-            }
+        match parse_makefile(value) {
+            Ok(("", target_graph)) => Ok(target_graph),
+            Ok((remaining, _)) => Err(ParseError::new(value, remaining)),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(ParseError::new(value, e.input)),
+            Err(nom::Err::Incomplete(_)) => Err(ParseError::new(value, "")),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::{
+        ParseError, PatternRule, RecipeLocation, TargetGraph, TargetMetadata, TargetName,
+    };
+
+    #[test]
+    fn test_parse_targets() {
+        let database =
+            "# Files\n\nworld: moon\n\tinner\n\nmoon:\n.DEFAULT_GOAL := world\n".to_owned();
+        let target_graph = TargetGraph::try_from(&database).unwrap();
+        assert_eq!(
+            target_graph.edges.get(&TargetName("world".to_owned())),
+            Some(&vec![TargetName("moon".to_owned())])
+        );
+        assert_eq!(
+            target_graph.default_goal,
+            Some(TargetName("world".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_parse_order_only_dependencies() {
+        let database = "out: in | dir\n".to_owned();
+        let target_graph = TargetGraph::try_from(&database).unwrap();
+        let out = TargetName("out".to_owned());
+        assert_eq!(
+            target_graph.edges.get(&out),
+            Some(&vec![TargetName("in".to_owned())])
+        );
+        assert_eq!(
+            target_graph.order_only_edges.get(&out),
+            Some(&vec![TargetName("dir".to_owned())])
+        );
+    }
+
+    #[test]
+    fn test_parse_target_metadata() {
+        let database = "# Not a target:\nsrc.c:\n#  Implicit rule search has been done.\n#  Last modified 2023-10-09 01:02:03.456\n\nworld: moon\n#  Phony target (prerequisite of .PHONY).\n#  File has been updated.\n# automatic\n# @ := world\n#  recipe to execute (from 'Makefile', line 12):\n\techo hi \\\n\t  there\n\t@touch world\n".to_owned();
+        let target_graph = TargetGraph::try_from(&database).unwrap();
+        assert_eq!(
+            target_graph.metadata.get(&TargetName("src.c".to_owned())),
+            Some(&TargetMetadata {
+                not_a_target: true,
+                implicit_rule_search_done: true,
+                last_modified: Some("2023-10-09 01:02:03.456".to_owned()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            target_graph.metadata.get(&TargetName("world".to_owned())),
+            Some(&TargetMetadata {
+                phony: true,
+                updated: true,
+                recipe_location: Some(RecipeLocation {
+                    file: "Makefile".to_owned(),
+                    line: 12
+                }),
+                recipe: vec![
+                    "echo hi \\".to_owned(),
+                    "  there".to_owned(),
+                    "@touch world".to_owned()
+                ],
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_pattern_rules() {
+        let database = "# Implicit Rules\n\n%.o: %.c | dir\n#  recipe to execute (from 'Makefile', line 3):\n\tcc -c $< -o $@\n".to_owned();
+        let target_graph = TargetGraph::try_from(&database).unwrap();
+        assert_eq!(
+            target_graph.pattern_rules,
+            vec![PatternRule {
+                target_patterns: vec!["%.o".to_owned()],
+                prerequisites: vec!["%.c".to_owned()],
+                order_only_prerequisites: vec!["dir".to_owned()],
+                recipe_location: Some(RecipeLocation {
+                    file: "Makefile".to_owned(),
+                    line: 3
+                }),
+                recipe: vec!["cc -c $< -o $@".to_owned()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let database = "world: moon\nhello: there : again\n".to_owned();
+        assert_eq!(
+            TargetGraph::try_from(&database).unwrap_err(),
+            ParseError {
+                line: 2,
+                column: 14,
+                line_text: "hello: there : again".to_owned(),
+                parser: "parse_makefile_target",
+                message: "expected end of line".to_owned(),
+            }
+        );
+    }
+}
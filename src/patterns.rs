@@ -0,0 +1,327 @@
+use std::{collections::BTreeSet, fs::read_dir, path::Path};
+
+use crate::parse::{PatternRule, TargetGraph, TargetMetadata, TargetName};
+
+/// How many pattern rules may be chained to make a single target (e.g. `%.o`
+/// from `%.c` from `%.y`).
+const MAX_CHAIN_LENGTH: usize = 4;
+
+/// A target that `make` would build using a pattern rule, with its
+/// prerequisites substituted.
+struct ResolvedTarget {
+    target_name: TargetName,
+    dependencies: Vec<TargetName>,
+    order_only_dependencies: Vec<TargetName>,
+    metadata: TargetMetadata,
+}
+
+impl TargetGraph {
+    /// Adds a target to the graph if it is not explicitly mentioned in the
+    /// Makefile but can be built using pattern rules, along with any
+    /// prerequisites that are needed for it. Returns whether the target is now
+    /// in the graph.
+    pub(crate) fn resolve_pattern_target(
+        &mut self,
+        target_name: &TargetName,
+        file_exists: &dyn Fn(&TargetName) -> bool,
+    ) -> bool {
+        if self.edges.contains_key(target_name) {
+            return true;
+        }
+        let Some(resolved_targets) = self.plan_pattern_target(target_name, 0, file_exists) else {
+            return false;
+        };
+        self.add_resolved_targets(resolved_targets);
+        true
+    }
+
+    /// Uses pattern rules for targets that are already in the graph without a
+    /// recipe, because `make` hasn't searched for an implicit rule for them yet
+    /// (e.g. `foo.o` in `app: foo.o`, or a `foo.o: foo.h` line without a recipe).
+    /// Like `make`, explicit prerequisites are kept in addition to the ones from the pattern rule.
+    pub(crate) fn resolve_pattern_prerequisites(
+        &mut self,
+        file_exists: &dyn Fn(&TargetName) -> bool,
+    ) {
+        let unresolved_target_names: Vec<TargetName> = self
+            .metadata
+            .iter()
+            .filter(|(_, metadata)| {
+                metadata.recipe.is_empty() && !metadata.implicit_rule_search_done && !metadata.phony
+            })
+            .map(|(target_name, _)| target_name.clone())
+            .collect();
+        for target_name in unresolved_target_names {
+            if let Some(resolved_targets) = self.plan_pattern_target(&target_name, 0, file_exists) {
+                self.add_resolved_targets(resolved_targets);
+            }
+        }
+    }
+
+    fn add_resolved_targets(&mut self, resolved_targets: Vec<ResolvedTarget>) {
+        for mut resolved_target in resolved_targets {
+            let target_name = resolved_target.target_name;
+            if let Some(explicit_dependencies) = self.edges.get(&target_name) {
+                merge_dependencies(&mut resolved_target.dependencies, explicit_dependencies);
+                if let Some(explicit_dependencies) = self.order_only_edges.get(&target_name) {
+                    merge_dependencies(
+                        &mut resolved_target.order_only_dependencies,
+                        explicit_dependencies,
+                    );
+                }
+                if let Some(explicit_metadata) = self.metadata.get(&target_name) {
+                    resolved_target.metadata = TargetMetadata {
+                        not_a_target: false,
+                        recipe_location: resolved_target.metadata.recipe_location,
+                        recipe: resolved_target.metadata.recipe,
+                        ..explicit_metadata.clone()
+                    };
+                }
+            }
+            if !resolved_target.order_only_dependencies.is_empty() {
+                self.order_only_edges
+                    .insert(target_name.clone(), resolved_target.order_only_dependencies);
+            }
+            self.metadata
+                .insert(target_name.clone(), resolved_target.metadata);
+            self.edges.insert(target_name, resolved_target.dependencies);
+        }
+    }
+
+    /// Whether any pattern rule has a target pattern that matches `target_name`
+    /// (regardless of whether its prerequisites exist).
+    pub(crate) fn matches_pattern_rule(&self, target_name: &TargetName) -> bool {
+        self.pattern_rules.iter().any(|pattern_rule| {
+            pattern_rule.target_patterns.iter().any(|target_pattern| {
+                match_target_pattern(target_pattern, &target_name.0).is_some()
+            })
+        })
+    }
+
+    /// Returns everything that needs to be added to the graph to build
+    /// `target_name`, or `None` if no pattern rule applies.
+    fn plan_pattern_target(
+        &self,
+        target_name: &TargetName,
+        chain_length: usize,
+        file_exists: &dyn Fn(&TargetName) -> bool,
+    ) -> Option<Vec<ResolvedTarget>> {
+        if chain_length >= MAX_CHAIN_LENGTH {
+            return None;
+        }
+
+        // Like `make`, prefer the rule with the shortest stem.
+        let mut candidates: Vec<(&PatternRule, String, String)> = self
+            .pattern_rules
+            .iter()
+            .filter_map(|pattern_rule| {
+                pattern_rule
+                    .target_patterns
+                    .iter()
+                    .find_map(|target_pattern| match_target_pattern(target_pattern, &target_name.0))
+                    .map(|(directory, stem)| (pattern_rule, directory, stem))
+            })
+            .collect();
+        candidates.sort_by_key(|(_, _, stem)| stem.len());
+
+        'candidates: for (pattern_rule, directory, stem) in candidates {
+            let expand = |prerequisites: &[String]| -> Vec<TargetName> {
+                prerequisites
+                    .iter()
+                    .map(|prerequisite| expand_prerequisite(prerequisite, &directory, &stem))
+                    .collect()
+            };
+            let dependencies = expand(&pattern_rule.prerequisites);
+            let order_only_dependencies = expand(&pattern_rule.order_only_prerequisites);
+
+            let mut resolved_targets = vec![];
+            for dependency in dependencies.iter().chain(order_only_dependencies.iter()) {
+                if self.edges.contains_key(dependency)
+                    || resolved_targets
+                        .iter()
+                        .any(|r: &ResolvedTarget| r.target_name == *dependency)
+                {
+                    continue;
+                }
+                if file_exists(dependency) {
+                    resolved_targets.push(ResolvedTarget {
+                        target_name: dependency.clone(),
+                        dependencies: vec![],
+                        order_only_dependencies: vec![],
+                        metadata: TargetMetadata {
+                            not_a_target: true,
+                            // Used as a source file, like `make` would after its search.
+                            implicit_rule_search_done: true,
+                            ..Default::default()
+                        },
+                    });
+                    continue;
+                }
+                match self.plan_pattern_target(dependency, chain_length + 1, file_exists) {
+                    Some(chained_targets) => resolved_targets.extend(chained_targets),
+                    None => continue 'candidates,
+                }
+            }
+
+            resolved_targets.push(ResolvedTarget {
+                target_name: target_name.clone(),
+                dependencies,
+                order_only_dependencies,
+                metadata: TargetMetadata {
+                    recipe_location: pattern_rule.recipe_location.clone(),
+                    recipe: pattern_rule.recipe.clone(),
+                    ..Default::default()
+                },
+            });
+            return Some(resolved_targets);
+        }
+        None
+    }
+
+    /// Files that could be built using a pattern rule from a file that exists,
+    /// e.g. `foo.o` if there is a `%.o: %.c` rule and a file called `foo.c`.
+    /// Only looks at the directory that each prerequisite pattern refers to,
+    /// relative to `make_directory`.
+    pub(crate) fn pattern_target_candidates(&self, make_directory: &Path) -> Vec<TargetName> {
+        let mut candidates = BTreeSet::<String>::new();
+        for pattern_rule in &self.pattern_rules {
+            let Some(prerequisite_pattern) = pattern_rule
+                .prerequisites
+                .iter()
+                .find(|prerequisite| prerequisite.contains('%'))
+            else {
+                continue;
+            };
+            let (prefix, _) = prerequisite_pattern
+                .split_once('%')
+                .expect("Pattern unexpectedly missing a `%`");
+            let directory = match prefix.rfind('/') {
+                Some(i) => &prefix[..i + 1],
+                None => "",
+            };
+            let Ok(entries) =
+                read_dir(make_directory.join(if directory.is_empty() { "." } else { directory }))
+            else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = format!("{}{}", directory, entry.file_name().to_string_lossy());
+                let Some(stem) = match_pattern(prerequisite_pattern, &path) else {
+                    continue;
+                };
+                if stem.contains('/') {
+                    continue;
+                }
+                for target_pattern in &pattern_rule.target_patterns {
+                    candidates.insert(target_pattern.replacen('%', stem, 1));
+                }
+            }
+        }
+        candidates
+            .into_iter()
+            .map(TargetName)
+            .filter(|target_name| !self.edges.contains_key(target_name))
+            .collect()
+    }
+}
+
+/// Appends the explicit dependencies that aren't already in `dependencies`.
+fn merge_dependencies(dependencies: &mut Vec<TargetName>, explicit_dependencies: &[TargetName]) {
+    for dependency in explicit_dependencies {
+        if !dependencies.contains(dependency) {
+            dependencies.push(dependency.clone());
+        }
+    }
+}
+
+/// Returns the stem if `name` matches `pattern` (e.g. `foo` for `%.c` and `foo.c`).
+fn match_pattern<'a>(pattern: &str, name: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = pattern.split_once('%')?;
+    name.strip_prefix(prefix)?.strip_suffix(suffix)
+}
+
+/// Like `make`, a target pattern without a slash is matched against the file
+/// name only, and the directory is prepended to the prerequisites afterwards.
+/// Returns the directory (possibly empty) and the stem.
+fn match_target_pattern(target_pattern: &str, target_name: &str) -> Option<(String, String)> {
+    if target_pattern.contains('/') {
+        let stem = match_pattern(target_pattern, target_name)?;
+        return Some(("".to_owned(), stem.to_owned()));
+    }
+    let (directory, file_name) = match target_name.rfind('/') {
+        Some(i) => target_name.split_at(i + 1),
+        None => ("", target_name),
+    };
+    let stem = match_pattern(target_pattern, file_name)?;
+    Some((directory.to_owned(), stem.to_owned()))
+}
+
+fn expand_prerequisite(prerequisite: &str, directory: &str, stem: &str) -> TargetName {
+    if !prerequisite.contains('%') {
+        return TargetName(prerequisite.to_owned());
+    }
+    TargetName(format!(
+        "{}{}",
+        directory,
+        prerequisite.replacen('%', stem, 1)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::{TargetGraph, TargetName};
+
+    #[test]
+    fn test_resolve_pattern_target() {
+        let database = "# Implicit Rules\n\n%.o: %.c | dir\n\n%.c: %.y\n\n# Files\n\ndir:\n\n# Not a target:\nMakefile:\n".to_owned();
+        let mut target_graph = TargetGraph::try_from(&database).unwrap();
+        let target_name = TargetName("src/foo.o".to_owned());
+        let no_files = |_: &TargetName| false;
+        assert!(
+            !target_graph.resolve_pattern_target(&TargetName("src/foo.x".to_owned()), &no_files)
+        );
+        // `src/foo.c` and `src/foo.y` don't exist.
+        assert!(!target_graph.resolve_pattern_target(&target_name, &no_files));
+
+        let file_exists = |target_name: &TargetName| target_name.0 == "src/foo.c";
+        assert!(target_graph.resolve_pattern_target(&target_name, &file_exists));
+        assert_eq!(
+            target_graph.edges.get(&target_name),
+            Some(&vec![TargetName("src/foo.c".to_owned())])
+        );
+        assert_eq!(
+            target_graph.order_only_edges.get(&target_name),
+            Some(&vec![TargetName("dir".to_owned())])
+        );
+        assert!(target_graph.metadata[&TargetName("src/foo.c".to_owned())].not_a_target);
+    }
+
+    #[test]
+    fn test_resolve_pattern_prerequisites() {
+        let database = "# Implicit Rules\n\n%.o: %.c\n#  recipe to execute (from 'Makefile', line 3):\n\tcc -c $< -o $@\n\n# Files\n\napp: foo.o bar.o baz.o\n\nbaz.o: baz.h\n#  Implicit rule search has not been done.\n\n# Not a target:\nfoo.o:\n#  Implicit rule search has not been done.\n\n# Not a target:\nbar.o:\n#  Implicit rule search has been done.\n".to_owned();
+        let mut target_graph = TargetGraph::try_from(&database).unwrap();
+        target_graph.resolve_pattern_prerequisites(&|target_name| {
+            ["foo.c", "bar.c", "baz.c", "baz.h"].contains(&target_name.0.as_str())
+        });
+
+        let foo_o = TargetName("foo.o".to_owned());
+        assert_eq!(
+            target_graph.edges.get(&foo_o),
+            Some(&vec![TargetName("foo.c".to_owned())])
+        );
+        assert!(!target_graph.metadata[&foo_o].not_a_target);
+        assert_eq!(target_graph.metadata[&foo_o].recipe, vec!["cc -c $< -o $@"]);
+        assert_eq!(
+            target_graph.edges.get(&TargetName("baz.o".to_owned())),
+            Some(&vec![
+                TargetName("baz.c".to_owned()),
+                TargetName("baz.h".to_owned())
+            ])
+        );
+        // `make` already looked for a rule for `bar.o` and found none.
+        assert_eq!(
+            target_graph.edges.get(&TargetName("bar.o".to_owned())),
+            Some(&vec![])
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    io,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use console::{measure_text_width, style, truncate_str, Key, Term};
+
+use libc::SIGINT;
+
+use crate::parse::{TargetGraph, TargetName};
+
+/// How many matching targets to show at once.
+const NUM_VISIBLE_TARGETS: usize = 10;
+/// How many lines of the recipe to show for the selected target.
+const NUM_RECIPE_LINES: usize = 3;
+
+/// Whether the picker can be shown, i.e. both reading keys and drawing are possible.
+pub(crate) fn can_pick_target() -> bool {
+    Term::stderr().is_term() && Term::stdout().is_term()
+}
+
+/// Lets the user choose a target interactively, by typing part of its name.
+/// Returns `None` if the user cancels (Esc or Ctrl-C).
+pub(crate) fn pick_target(target_graph: &TargetGraph) -> Option<TargetName> {
+    let target_names: Vec<&TargetName> = target_graph
+        .edges
+        .keys()
+        .filter(|target_name| {
+            !target_graph
+                .metadata
+                .get(*target_name)
+                .is_some_and(|metadata| metadata.not_a_target)
+        })
+        .collect();
+    let mut picker = Picker {
+        term: Term::stderr(),
+        target_graph,
+        target_names,
+        query: String::new(),
+        selected_index: 0,
+        num_drawn_lines: 0,
+    };
+    // `console` raises `SIGINT` on Ctrl-C, which would otherwise kill `mak`
+    // before the picker is cleared. With a handler, reading the key fails instead.
+    let sigint_id = signal_hook::flag::register(SIGINT, Arc::new(AtomicBool::new(false)))
+        .expect("Could not register signal handler.");
+    let _ = picker.term.hide_cursor();
+    let result = picker.run();
+    let _ = picker.clear();
+    let _ = picker.term.show_cursor();
+    signal_hook::low_level::unregister(sigint_id);
+    result.ok().flatten()
+}
+
+struct Picker<'a> {
+    term: Term,
+    target_graph: &'a TargetGraph,
+    target_names: Vec<&'a TargetName>,
+    query: String,
+    /// Index into the matching targets.
+    selected_index: usize,
+    num_drawn_lines: usize,
+}
+
+impl<'a> Picker<'a> {
+    fn run(&mut self) -> io::Result<Option<TargetName>> {
+        loop {
+            let matches = self.matches();
+            self.selected_index = self.selected_index.min(matches.len().saturating_sub(1));
+            self.draw(&matches)?;
+            match self.term.read_key()? {
+                Key::Enter => {
+                    if let Some(target_name) = matches.get(self.selected_index) {
+                        return Ok(Some((*target_name).clone()));
+                    }
+                }
+                Key::Escape => return Ok(None),
+                Key::ArrowUp | Key::BackTab => {
+                    self.selected_index = self.selected_index.saturating_sub(1)
+                }
+                Key::ArrowDown | Key::Tab => self.selected_index += 1,
+                Key::Backspace => {
+                    self.query.pop();
+                    self.selected_index = 0;
+                }
+                Key::Char(c) if !c.is_control() => {
+                    self.query.push(c);
+                    self.selected_index = 0;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Matching targets, best match first.
+    fn matches(&self) -> Vec<&'a TargetName> {
+        let mut scored_matches: Vec<(usize, &TargetName)> = self
+            .target_names
+            .iter()
+            .filter_map(|target_name| {
+                fuzzy_match_score(&self.query, &target_name.0).map(|score| (score, *target_name))
+            })
+            .collect();
+        // Stable, so that targets keep their Makefile order among equally good matches.
+        scored_matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored_matches
+            .into_iter()
+            .map(|(_, target_name)| target_name)
+            .collect()
+    }
+
+    fn draw(&mut self, matches: &[&TargetName]) -> io::Result<()> {
+        let width = self.term.size().1 as usize;
+        let mut lines = vec![
+            style("Pick a target to build (type to filter, ↑/↓ to select, Enter to build, Esc to cancel):")
+                .bold()
+                .to_string(),
+            format!("{} {}", style("❯").cyan(), self.query),
+        ];
+        // Scroll so that the selected target is visible.
+        let first_visible_index = self.selected_index.saturating_sub(NUM_VISIBLE_TARGETS - 1);
+        for (i, target_name) in matches
+            .iter()
+            .enumerate()
+            .skip(first_visible_index)
+            .take(NUM_VISIBLE_TARGETS)
+        {
+            if i == self.selected_index {
+                lines.push(format!(
+                    "  {} {}",
+                    style("▶").cyan(),
+                    style(target_name).cyan()
+                ));
+            } else {
+                lines.push(format!("    {}", target_name));
+            }
+        }
+        if matches.is_empty() {
+            lines.push(format!("    {}", style("(no matching targets)").dim()));
+        }
+        if let Some(target_name) = matches.get(self.selected_index) {
+            lines.extend(self.preview(target_name));
+        }
+
+        self.clear()?;
+        for line in &lines {
+            self.term
+                .write_line(&truncate_str(line, width.saturating_sub(1), "…"))?;
+        }
+        self.num_drawn_lines = lines.len();
+        self.term.flush()
+    }
+
+    /// The prerequisites and the start of the recipe of a target.
+    fn preview(&self, target_name: &TargetName) -> Vec<String> {
+        let mut lines = vec![];
+        let dependencies = self
+            .target_graph
+            .all_dependencies(target_name)
+            .unwrap_or_default();
+        let dependency_names: Vec<String> = dependencies.iter().map(|t| t.to_string()).collect();
+        lines.push(format!(
+            "  {} {}",
+            style("Prerequisites:").dim(),
+            if dependency_names.is_empty() {
+                "(none)".to_owned()
+            } else {
+                dependency_names.join(" ")
+            }
+        ));
+        let Some(metadata) = self.target_graph.metadata.get(target_name) else {
+            return lines;
+        };
+        if metadata.recipe.is_empty() {
+            return lines;
+        }
+        let location = match &metadata.recipe_location {
+            Some(recipe_location) => {
+                format!(" ({}:{})", recipe_location.file, recipe_location.line)
+            }
+            None => "".to_owned(),
+        };
+        lines.push(format!("  {}", style(format!("Recipe{}:", location)).dim()));
+        for recipe_line in metadata.recipe.iter().take(NUM_RECIPE_LINES) {
+            lines.push(format!("    {}", recipe_line));
+        }
+        if metadata.recipe.len() > NUM_RECIPE_LINES {
+            lines.push(format!("    {}", style("…").dim()));
+        }
+        lines
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.term.clear_last_lines(self.num_drawn_lines)?;
+        self.num_drawn_lines = 0;
+        Ok(())
+    }
+}
+
+/// Returns a score if every character of `query` appears in `candidate` in
+/// order (ignoring case). Higher is better: consecutive matches and matches
+/// at the start of a word (after `-`, `_`, `/`, or `.`) score more.
+fn fuzzy_match_score(query: &str, candidate: &str) -> Option<usize> {
+    let candidate_chars: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut candidate_index = 0;
+    let mut previous_match_index: Option<usize> = None;
+    for query_char in query.chars() {
+        let query_char = query_char.to_ascii_lowercase();
+        loop {
+            let candidate_char = *candidate_chars.get(candidate_index)?;
+            if candidate_char.to_ascii_lowercase() == query_char {
+                break;
+            }
+            candidate_index += 1;
+        }
+        score += 1;
+        if previous_match_index.is_some_and(|i| i + 1 == candidate_index) {
+            score += 3;
+        }
+        if candidate_index == 0 || "-_/.".contains(candidate_chars[candidate_index - 1]) {
+            score += 2;
+        }
+        previous_match_index = Some(candidate_index);
+        candidate_index += 1;
+    }
+    // Prefer shorter candidates among otherwise equal matches.
+    Some(score * 1000 + 1000usize.saturating_sub(measure_text_width(candidate)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fuzzy_match_score;
+
+    #[test]
+    fn test_fuzzy_match_score() {
+        assert!(fuzzy_match_score("bjs", "build-lib-js").is_some());
+        assert!(fuzzy_match_score("jsb", "build-lib-js").is_none());
+        assert!(fuzzy_match_score("", "anything").is_some());
+        assert!(fuzzy_match_score("lib", "build-lib-js") > fuzzy_match_score("lib", "build-lxixb"));
+        assert!(fuzzy_match_score("test", "test") > fuzzy_match_score("test", "test-all"));
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressState, ProgressStyle};
+
+use crate::{history::BuildEstimate, parse::TargetName, OutputLine};
+
+/// Shows the progress of a build.
+pub(crate) trait Renderer: Send + Sync {
+    /// Called when a target is scheduled, before its dependencies have finished.
+    fn add_target(&self, target_name: &TargetName, depth: usize) -> Box<dyn TargetRenderer>;
+    /// Called when a rebuild starts, e.g. after a change while watching.
+    fn build_started(&self, _start_time: Instant, _build_estimate: Option<Arc<BuildEstimate>>) {}
+    /// Called once every target has finished.
+    fn build_finished(&self) {}
+}
+
+/// Shows the progress of a single target. Exactly one of the `finish_…`
+/// methods is called at the end.
+pub(crate) trait TargetRenderer: Send + Sync {
+    /// All dependencies have finished, but no job slot is available yet.
+    fn waiting_for_job(&self);
+    /// `make` has been invoked.
+    fn started(&self);
+    fn output_line(&self, output_line: &OutputLine);
+    /// `dry_run_commands` are the commands that `make` would have run, in a dry run.
+    fn finish_succeeded(&self, dry_run_commands: Option<Vec<String>>);
+    fn finish_up_to_date(&self);
+    fn finish_failed(&self, log_path: Option<&Path>);
+    fn finish_skipped(&self);
+    fn finish_cancelled(&self);
+}
+
+/// Prints an output line prefixed by its target, on the corresponding stream.
+pub(crate) fn print_live_output_line(target_name: &TargetName, output_line: &OutputLine) {
+    match output_line {
+        OutputLine::Stdout(line) => println!("[{}] {}", target_name, line),
+        OutputLine::Stderr(line) => eprintln!("[{}] {}", target_name, line),
+    }
+}
+
+/// Live-updating progress bars, for interactive terminals.
+pub(crate) struct FancyRenderer {
+    multi_progress: MultiProgress,
+    stream_output: bool,
+    /// Shows the estimated time remaining above the targets, if available.
+    header: Mutex<Option<(ProgressBar, Arc<BuildEstimate>)>>,
+}
+
+impl FancyRenderer {
+    pub(crate) fn new(stream_output: bool, build_estimate: Option<Arc<BuildEstimate>>) -> Self {
+        let multi_progress = MultiProgress::new();
+        let header = Self::add_header(&multi_progress, build_estimate);
+        Self {
+            multi_progress,
+            stream_output,
+            header: Mutex::new(header),
+        }
+    }
+
+    fn add_header(
+        multi_progress: &MultiProgress,
+        build_estimate: Option<Arc<BuildEstimate>>,
+    ) -> Option<(ProgressBar, Arc<BuildEstimate>)> {
+        build_estimate.map(|build_estimate| {
+            let header = multi_progress.add(ProgressBar::new_spinner());
+            let build_estimate_owned = build_estimate.clone();
+            header.set_style(
+                ProgressStyle::with_template("     ⏱️   {time_remaining}")
+                    .expect("Could not construct progress bar template.")
+                    .with_key(
+                        "time_remaining",
+                        move |_: &ProgressState, w: &mut dyn std::fmt::Write| {
+                            let _ = write!(
+                                w,
+                                "About {:.1}s remaining (based on previous runs)",
+                                build_estimate_owned.time_remaining().as_secs_f64()
+                            );
+                        },
+                    ),
+            );
+            header.enable_steady_tick(Duration::from_millis(100));
+            (header, build_estimate)
+        })
+    }
+
+    fn lock_header(&self) -> std::sync::MutexGuard<'_, Option<(ProgressBar, Arc<BuildEstimate>)>> {
+        self.header
+            .lock()
+            .expect("Could not access progress header.")
+    }
+}
+
+impl Renderer for FancyRenderer {
+    fn add_target(&self, target_name: &TargetName, depth: usize) -> Box<dyn TargetRenderer> {
+        let progress_bar = ProgressBar::new(2);
+        let progress_bar = self.multi_progress.insert_from_back(0, progress_bar);
+        progress_bar.set_style(
+            ProgressStyle::with_template("     ⋯    {prefix}")
+                .expect("Could not construct progress bar template."),
+        );
+        let progress_bar = progress_bar.with_finish(ProgressFinish::AndLeave);
+        let indentation = match depth {
+            0 => "🎯".to_owned(),
+            depth => format!("{}{} ", "  ".repeat(depth), "↙"),
+        };
+        progress_bar.set_prefix(format!("{}{}", indentation, target_name));
+        progress_bar.set_position(0);
+        Box::new(FancyTargetRenderer {
+            multi_progress: self.stream_output.then(|| self.multi_progress.clone()),
+            target_name: target_name.clone(),
+            progress_bar,
+            build_estimate: self
+                .lock_header()
+                .as_ref()
+                .map(|(_, build_estimate)| build_estimate.clone()),
+        })
+    }
+
+    /// Shows a new header, since the previous one was removed when its build finished.
+    fn build_started(&self, _start_time: Instant, build_estimate: Option<Arc<BuildEstimate>>) {
+        *self.lock_header() = Self::add_header(&self.multi_progress, build_estimate);
+    }
+
+    fn build_finished(&self) {
+        if let Some((header, _)) = self.lock_header().take() {
+            header.finish_and_clear();
+            self.multi_progress.remove(&header);
+        }
+    }
+}
+
+struct FancyTargetRenderer {
+    /// Only set when streaming output.
+    multi_progress: Option<MultiProgress>,
+    target_name: TargetName,
+    progress_bar: ProgressBar,
+    build_estimate: Option<Arc<BuildEstimate>>,
+}
+
+impl FancyTargetRenderer {
+    fn finish_with_template(&self, template: &str) {
+        if let Some(build_estimate) = &self.build_estimate {
+            build_estimate.finished(&self.target_name);
+        }
+        self.progress_bar.set_style(
+            ProgressStyle::with_template(template)
+                .expect("Could not construct progress bar template."),
+        );
+        self.progress_bar.finish();
+    }
+}
+
+impl TargetRenderer for FancyTargetRenderer {
+    fn waiting_for_job(&self) {
+        self.progress_bar.set_style(
+            ProgressStyle::with_template("     ⏳   {prefix}")
+                .expect("Could not construct progress bar template."),
+        );
+    }
+
+    fn started(&self) {
+        if let Some(build_estimate) = &self.build_estimate {
+            build_estimate.started(&self.target_name);
+        }
+        self.progress_bar.reset_elapsed();
+        self.progress_bar.set_position(1);
+        self.progress_bar.set_style(
+            ProgressStyle::with_template("{elapsed:>06} {spinner}  {prefix:40} 🛠️ | {wide_msg}")
+                .expect("Could not construct progress bar."),
+        );
+        self.progress_bar
+            .enable_steady_tick(Duration::from_millis(16));
+    }
+
+    fn output_line(&self, output_line: &OutputLine) {
+        let (OutputLine::Stdout(line) | OutputLine::Stderr(line)) = output_line;
+        if !line.trim().is_empty() {
+            self.progress_bar.set_message(line.clone())
+        };
+        if let Some(multi_progress) = &self.multi_progress {
+            if multi_progress.is_hidden() {
+                print_live_output_line(&self.target_name, output_line);
+            } else {
+                // Ignore failures, since there is nowhere else to print the line to.
+                let _ = multi_progress.println(format!("[{}] {}", self.target_name, line));
+            }
+        }
+    }
+
+    fn finish_succeeded(&self, dry_run_commands: Option<Vec<String>>) {
+        self.progress_bar.set_position(2);
+        match dry_run_commands {
+            Some(commands) => {
+                // Show the commands that would have been run, in place of the live output.
+                self.progress_bar.set_message(commands.join(" ⏎ "));
+                self.finish_with_template("{elapsed:>06} 📝 {prefix:40} | {wide_msg}");
+            }
+            None => self.finish_with_template("{elapsed:>06} ✅ {prefix}"),
+        }
+    }
+
+    fn finish_up_to_date(&self) {
+        self.finish_with_template("     ✅   {prefix:40} (up to date)");
+    }
+
+    fn finish_failed(&self, log_path: Option<&Path>) {
+        self.progress_bar.set_position(2);
+        match log_path {
+            Some(log_path) => {
+                self.progress_bar
+                    .set_message(format!("log: {}", log_path.display()));
+                self.finish_with_template("{elapsed:>06} ❌ {prefix:40} | {wide_msg}");
+            }
+            None => self.finish_with_template("{elapsed:>06} ❌ {prefix}"),
+        }
+    }
+
+    fn finish_skipped(&self) {
+        self.finish_with_template("     ⏭️    {prefix}");
+    }
+
+    fn finish_cancelled(&self) {
+        self.finish_with_template("{elapsed:>06} 🚫 {prefix}");
+    }
+}
+
+/// One line per started or finished target, for logs (e.g. in CI).
+pub(crate) struct PlainRenderer {
+    start_time: Mutex<Instant>,
+    stream_output: bool,
+}
+
+impl PlainRenderer {
+    pub(crate) fn new(start_time: Instant, stream_output: bool) -> Self {
+        Self {
+            start_time: Mutex::new(start_time),
+            stream_output,
+        }
+    }
+}
+
+impl Renderer for PlainRenderer {
+    fn add_target(&self, target_name: &TargetName, _depth: usize) -> Box<dyn TargetRenderer> {
+        Box::new(PlainTargetRenderer {
+            build_start_time: *self
+                .start_time
+                .lock()
+                .expect("Could not access build start time."),
+            stream_output: self.stream_output,
+            target_name: target_name.clone(),
+            target_start_time: Mutex::new(None),
+        })
+    }
+
+    fn build_started(&self, start_time: Instant, _build_estimate: Option<Arc<BuildEstimate>>) {
+        *self
+            .start_time
+            .lock()
+            .expect("Could not access build start time.") = start_time;
+    }
+}
+
+struct PlainTargetRenderer {
+    build_start_time: Instant,
+    stream_output: bool,
+    target_name: TargetName,
+    target_start_time: Mutex<Option<Instant>>,
+}
+
+impl PlainTargetRenderer {
+    /// e.g. `[00:03.2] ✅ build-lib-js (1.5s)`
+    fn print(&self, emoji: &str, suffix: &str) {
+        let elapsed = Instant::now() - self.build_start_time;
+        println!(
+            "[{:02}:{:04.1}] {} {}{}",
+            elapsed.as_secs() / 60,
+            elapsed.as_secs_f64() % 60.0,
+            emoji,
+            self.target_name,
+            suffix
+        );
+    }
+
+    fn duration_suffix(&self) -> String {
+        match *self
+            .target_start_time
+            .lock()
+            .expect("Could not access target start time.")
+        {
+            Some(target_start_time) => {
+                format!(
+                    " ({:.1}s)",
+                    (Instant::now() - target_start_time).as_secs_f64()
+                )
+            }
+            None => "".to_owned(),
+        }
+    }
+}
+
+impl TargetRenderer for PlainTargetRenderer {
+    fn waiting_for_job(&self) {}
+
+    fn started(&self) {
+        *self
+            .target_start_time
+            .lock()
+            .expect("Could not access target start time.") = Some(Instant::now());
+        self.print("🛠️ ", "");
+    }
+
+    fn output_line(&self, output_line: &OutputLine) {
+        if self.stream_output {
+            print_live_output_line(&self.target_name, output_line);
+        }
+    }
+
+    fn finish_succeeded(&self, dry_run_commands: Option<Vec<String>>) {
+        match dry_run_commands {
+            Some(commands) => self.print("📝", &format!(" | {}", commands.join(" ⏎ "))),
+            None => self.print("✅", &self.duration_suffix()),
+        }
+    }
+
+    fn finish_up_to_date(&self) {
+        self.print("✅", " (up to date)");
+    }
+
+    fn finish_failed(&self, log_path: Option<&Path>) {
+        let log_suffix = match log_path {
+            Some(log_path) => format!(" | log: {}", log_path.display()),
+            None => "".to_owned(),
+        };
+        self.print("❌", &format!("{}{}", self.duration_suffix(), log_suffix));
+    }
+
+    fn finish_skipped(&self) {
+        self.print("⏭️ ", " (skipped)");
+    }
+
+    fn finish_cancelled(&self) {
+        self.print("🚫", " (cancelled)");
+    }
+}
+
+/// Shows nothing except streamed output (if enabled).
+pub(crate) struct HiddenRenderer {
+    pub(crate) stream_output: bool,
+}
+
+impl Renderer for HiddenRenderer {
+    fn add_target(&self, target_name: &TargetName, _depth: usize) -> Box<dyn TargetRenderer> {
+        Box::new(HiddenTargetRenderer {
+            stream_output: self.stream_output,
+            target_name: target_name.clone(),
+        })
+    }
+}
+
+struct HiddenTargetRenderer {
+    stream_output: bool,
+    target_name: TargetName,
+}
+
+impl TargetRenderer for HiddenTargetRenderer {
+    fn waiting_for_job(&self) {}
+    fn started(&self) {}
+    fn output_line(&self, output_line: &OutputLine) {
+        if self.stream_output {
+            print_live_output_line(&self.target_name, output_line);
+        }
+    }
+    fn finish_succeeded(&self, _dry_run_commands: Option<Vec<String>>) {}
+    fn finish_up_to_date(&self) {}
+    fn finish_failed(&self, _log_path: Option<&Path>) {}
+    fn finish_skipped(&self) {}
+    fn finish_cancelled(&self) {}
+}
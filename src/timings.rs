@@ -0,0 +1,158 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use crate::parse::{TargetGraph, TargetName};
+
+/// How many targets to list in the summary.
+const NUM_SLOWEST_TARGETS: usize = 5;
+
+/// How long the recipe of each target took to run.
+#[derive(Default)]
+pub(crate) struct TargetTimings {
+    durations: Mutex<HashMap<TargetName, Duration>>,
+}
+
+impl TargetTimings {
+    pub(crate) fn record(&self, target_name: &TargetName, duration: Duration) {
+        self.durations
+            .lock()
+            .expect("Could not access target timings.")
+            .insert(target_name.clone(), duration);
+    }
+
+    /// Targets that were not run (e.g. because they were up to date) count as taking no time.
+    pub(crate) fn duration(&self, target_name: &TargetName) -> Duration {
+        self.durations
+            .lock()
+            .expect("Could not access target timings.")
+            .get(target_name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The chain of dependencies (ending in one of `root_target_names`) with
+    /// the longest total duration, which bounds how fast the build can be no
+    /// matter how many jobs are available.
+    pub(crate) fn critical_path(
+        &self,
+        target_graph: &TargetGraph,
+        root_target_names: &[TargetName],
+    ) -> Vec<TargetName> {
+        let mut memo = HashMap::<TargetName, (Duration, Option<TargetName>)>::new();
+        let mut path = vec![];
+        let mut next = root_target_names
+            .iter()
+            .max_by_key(|target_name| self.path_duration(target_graph, target_name, &mut memo))
+            .cloned();
+        while let Some(target_name) = next {
+            next = memo
+                .get(&target_name)
+                .and_then(|(_, slowest)| slowest.clone());
+            path.push(target_name);
+        }
+        path.reverse();
+        path
+    }
+
+    /// Memoizes the duration of the critical path ending in `target_name`,
+    /// along with the dependency that it goes through.
+    fn path_duration(
+        &self,
+        target_graph: &TargetGraph,
+        target_name: &TargetName,
+        memo: &mut HashMap<TargetName, (Duration, Option<TargetName>)>,
+    ) -> Duration {
+        if let Some((duration, _)) = memo.get(target_name) {
+            return *duration;
+        }
+        let mut slowest: Option<(Duration, TargetName)> = None;
+        for dependency in target_graph
+            .all_dependencies(target_name)
+            .unwrap_or_default()
+        {
+            let duration = self.path_duration(target_graph, &dependency, memo);
+            if slowest.as_ref().is_none_or(|(max, _)| duration > *max) {
+                slowest = Some((duration, dependency));
+            }
+        }
+        let (dependency_duration, slowest_dependency) = match slowest {
+            Some((duration, dependency)) => (duration, Some(dependency)),
+            None => (Duration::ZERO, None),
+        };
+        let duration = dependency_duration + self.duration(target_name);
+        memo.insert(target_name.clone(), (duration, slowest_dependency));
+        duration
+    }
+
+    /// Prints the critical path, the slowest targets, and how well the build
+    /// was parallelized.
+    pub(crate) fn print_summary(
+        &self,
+        target_graph: &TargetGraph,
+        root_target_names: &[TargetName],
+        wall_clock_duration: Duration,
+    ) {
+        let critical_path = self.critical_path(target_graph, root_target_names);
+        let critical_path_duration: Duration = critical_path
+            .iter()
+            .map(|target_name| self.duration(target_name))
+            .sum();
+        println!(
+            "⏱️  Critical path ({}):",
+            format_duration(critical_path_duration)
+        );
+        for target_name in &critical_path {
+            println!(
+                "    {:>8}  {}",
+                format_duration(self.duration(target_name)),
+                target_name
+            );
+        }
+
+        let durations = self
+            .durations
+            .lock()
+            .expect("Could not access target timings.");
+        let mut slowest_targets: Vec<(&TargetName, &Duration)> = durations.iter().collect();
+        slowest_targets.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.0.cmp(&b_name.0)));
+        println!("⏱️  Slowest targets:");
+        for (target_name, duration) in slowest_targets.iter().take(NUM_SLOWEST_TARGETS) {
+            println!("    {:>8}  {}", format_duration(**duration), target_name);
+        }
+
+        let total_duration: Duration = durations.values().sum();
+        println!(
+            "⏱️  Parallelism: {:.1}× ({} of recipes in {} of wall-clock time)",
+            total_duration.as_secs_f64() / wall_clock_duration.as_secs_f64().max(f64::EPSILON),
+            format_duration(total_duration),
+            format_duration(wall_clock_duration)
+        );
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}s", duration.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::parse::{TargetGraph, TargetName};
+
+    use super::TargetTimings;
+
+    #[test]
+    fn test_critical_path() {
+        let database = "all: a b\na: c\nb: c d\nc:\nd:\n".to_owned();
+        let target_graph = TargetGraph::try_from(&database).unwrap();
+        let target_name = |name: &str| TargetName(name.to_owned());
+        let target_timings = TargetTimings::default();
+        for (name, millis) in [("all", 1), ("a", 5), ("b", 2), ("c", 3), ("d", 7)] {
+            target_timings.record(&target_name(name), Duration::from_millis(millis));
+        }
+        assert_eq!(
+            target_timings.critical_path(&target_graph, &[target_name("all")]),
+            vec![target_name("d"), target_name("b"), target_name("all")]
+        );
+    }
+}
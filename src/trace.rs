@@ -0,0 +1,208 @@
+use std::{fs, path::Path, sync::Mutex, time::Instant};
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::{parse::TargetName, TargetStatus};
+
+/// An event in the Chrome Trace Event format, which can be viewed using
+/// `chrome://tracing` or https://ui.perfetto.dev/
+#[derive(Serialize)]
+struct TraceEvent {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cat: Option<&'static str>,
+    /// `X` for a complete event (with a duration), `M` for metadata.
+    ph: &'static str,
+    /// Microseconds since the start of the build.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ts: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<u128>,
+    pid: u32,
+    tid: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TraceFile<'a> {
+    trace_events: &'a [TraceEvent],
+    display_time_unit: &'static str,
+}
+
+/// Records when each target waited and ran, for `--trace`. Each target gets
+/// its own track, so that overlapping spans show how much ran in parallel.
+pub(crate) struct BuildTrace {
+    start_time: Instant,
+    events: Mutex<Vec<TraceEvent>>,
+    tracks: Mutex<IndexMap<TargetName, usize>>,
+}
+
+impl BuildTrace {
+    pub(crate) fn new(start_time: Instant) -> Self {
+        Self {
+            start_time,
+            events: Mutex::new(vec![]),
+            tracks: Mutex::new(IndexMap::new()),
+        }
+    }
+
+    fn track(&self, target_name: &TargetName) -> usize {
+        let mut tracks = self.tracks.lock().expect("Could not access trace tracks.");
+        let num_tracks = tracks.len();
+        *tracks.entry(target_name.clone()).or_insert(num_tracks + 1)
+    }
+
+    fn micros_since_start(&self, instant: Instant) -> u128 {
+        instant
+            .saturating_duration_since(self.start_time)
+            .as_micros()
+    }
+
+    /// Records the dependency wait of a target.
+    pub(crate) fn dependency_wait(&self, target_name: &TargetName, start: Instant, end: Instant) {
+        self.span(
+            target_name,
+            "waiting for dependencies",
+            "wait",
+            start,
+            end,
+            None,
+        );
+    }
+
+    /// Records the wait for a job slot (e.g. due to `--jobs`).
+    pub(crate) fn job_wait(&self, target_name: &TargetName, start: Instant, end: Instant) {
+        self.span(
+            target_name,
+            "waiting for a job slot",
+            "wait",
+            start,
+            end,
+            None,
+        );
+    }
+
+    /// Records the `make` invocation of a target.
+    pub(crate) fn recipe(
+        &self,
+        target_name: &TargetName,
+        start: Instant,
+        end: Instant,
+        status: TargetStatus,
+    ) {
+        self.span(
+            target_name,
+            &target_name.0,
+            "recipe",
+            start,
+            end,
+            Some(serde_json::json!({ "status": status })),
+        );
+    }
+
+    fn span(
+        &self,
+        target_name: &TargetName,
+        name: &str,
+        category: &'static str,
+        start: Instant,
+        end: Instant,
+        args: Option<serde_json::Value>,
+    ) {
+        let tid = self.track(target_name);
+        let ts = self.micros_since_start(start);
+        let dur = self.micros_since_start(end).saturating_sub(ts);
+        self.events
+            .lock()
+            .expect("Could not access trace events.")
+            .push(TraceEvent {
+                name: name.to_owned(),
+                cat: Some(category),
+                ph: "X",
+                ts: Some(ts),
+                dur: Some(dur),
+                pid: std::process::id(),
+                tid,
+                args,
+            });
+    }
+
+    fn trace_events(&self) -> Vec<TraceEvent> {
+        let mut trace_events =
+            std::mem::take(&mut *self.events.lock().expect("Could not access trace events."));
+        // Name each track after its target.
+        let tracks = self.tracks.lock().expect("Could not access trace tracks.");
+        for (target_name, tid) in tracks.iter() {
+            trace_events.push(TraceEvent {
+                name: "thread_name".to_owned(),
+                cat: None,
+                ph: "M",
+                ts: None,
+                dur: None,
+                pid: std::process::id(),
+                tid: *tid,
+                args: Some(serde_json::json!({ "name": target_name })),
+            });
+            trace_events.push(TraceEvent {
+                name: "thread_sort_index".to_owned(),
+                cat: None,
+                ph: "M",
+                ts: None,
+                dur: None,
+                pid: std::process::id(),
+                tid: *tid,
+                args: Some(serde_json::json!({ "sort_index": tid })),
+            });
+        }
+        trace_events
+    }
+
+    pub(crate) fn write(&self, path: &Path) {
+        let trace_events = self.trace_events();
+        let trace_file = TraceFile {
+            trace_events: &trace_events,
+            display_time_unit: "ms",
+        };
+        let json = serde_json::to_string(&trace_file).expect("Could not serialize trace.");
+        if let Err(e) = fs::write(path, json) {
+            eprintln!("Could not write trace to {} ({})", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{parse::TargetName, TargetStatus};
+
+    use super::BuildTrace;
+
+    #[test]
+    fn test_build_trace() {
+        let start_time = Instant::now();
+        let build_trace = BuildTrace::new(start_time);
+        let a = TargetName("a".to_owned());
+        let b = TargetName("b".to_owned());
+        let ms = Duration::from_millis;
+        build_trace.recipe(&a, start_time, start_time + ms(5), TargetStatus::Succeeded);
+        build_trace.dependency_wait(&b, start_time, start_time + ms(5));
+        build_trace.recipe(
+            &b,
+            start_time + ms(5),
+            start_time + ms(7),
+            TargetStatus::Failed,
+        );
+
+        let trace_events = serde_json::to_value(build_trace.trace_events()).unwrap();
+        assert_eq!(trace_events[2]["name"], "b");
+        assert_eq!(trace_events[2]["ts"], 5000);
+        assert_eq!(trace_events[2]["dur"], 2000);
+        assert_eq!(trace_events[2]["tid"], 2);
+        assert_eq!(trace_events[2]["args"]["status"], "failed");
+        assert_eq!(trace_events[5]["args"]["name"], "b");
+    }
+}
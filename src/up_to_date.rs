@@ -0,0 +1,152 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::metadata,
+    path::Path,
+    time::SystemTime,
+};
+
+use crate::parse::{TargetGraph, TargetName};
+
+/// Determines which targets `make` would not need to remake, so that they can
+/// be marked as up to date without spawning `make` for them.
+///
+/// This mirrors `make`'s own rules: a target is up to date if it is not phony,
+/// its file exists, and every normal prerequisite is up to date and not newer
+/// than it. Order-only prerequisites are ignored, since they never make a
+/// target out of date. Files are relative to `make_directory`.
+pub(crate) fn find_up_to_date_targets(
+    target_graph: &TargetGraph,
+    make_directory: &Path,
+) -> HashSet<TargetName> {
+    let mut checker = UpToDateChecker {
+        target_graph,
+        make_directory,
+        results: HashMap::default(),
+    };
+    target_graph
+        .edges
+        .keys()
+        .filter(|target_name| checker.is_up_to_date(target_name))
+        .cloned()
+        .collect()
+}
+
+struct UpToDateChecker<'a> {
+    target_graph: &'a TargetGraph,
+    make_directory: &'a Path,
+    /// `None` while a target is being checked, to avoid infinite recursion on cycles.
+    results: HashMap<TargetName, Option<bool>>,
+}
+
+impl UpToDateChecker<'_> {
+    fn is_up_to_date(&mut self, target_name: &TargetName) -> bool {
+        match self.results.get(target_name) {
+            Some(Some(result)) => return *result,
+            Some(None) => return false,
+            None => {}
+        }
+        self.results.insert(target_name.clone(), None);
+        let result = self.check(target_name);
+        self.results.insert(target_name.clone(), Some(result));
+        result
+    }
+
+    fn check(&mut self, target_name: &TargetName) -> bool {
+        let Some(target_metadata) = self.target_graph.metadata.get(target_name) else {
+            return false;
+        };
+        if target_metadata.phony {
+            return false;
+        }
+        let Some(modified) = modified_time(self.make_directory, target_name) else {
+            return false;
+        };
+        if target_metadata.not_a_target {
+            // If `make` has looked for a rule to remake it and found none, it
+            // only needs to exist. Otherwise, leave it to `make`.
+            return target_metadata.implicit_rule_search_done
+                && !self.target_graph.matches_pattern_rule(target_name);
+        }
+        let Some(dependencies) = self.target_graph.edges.get(target_name) else {
+            return false;
+        };
+        dependencies.iter().all(|dependency| {
+            self.is_up_to_date(dependency)
+                && modified_time(self.make_directory, dependency)
+                    .is_some_and(|dependency_modified| dependency_modified <= modified)
+        })
+    }
+}
+
+/// The dependencies whose files are newer than the target's file, e.g. because
+/// they were just rebuilt or edited. Files are relative to `make_directory`.
+pub(crate) fn newer_dependencies(
+    make_directory: &Path,
+    target_name: &TargetName,
+    dependencies: &[TargetName],
+) -> Vec<TargetName> {
+    let Some(target_modified_time) = modified_time(make_directory, target_name) else {
+        return vec![];
+    };
+    dependencies
+        .iter()
+        .filter(|dependency| {
+            modified_time(make_directory, dependency)
+                .is_some_and(|modified_time| modified_time > target_modified_time)
+        })
+        .cloned()
+        .collect()
+}
+
+fn modified_time(make_directory: &Path, target_name: &TargetName) -> Option<SystemTime> {
+    metadata(make_directory.join(&target_name.0))
+        .ok()?
+        .modified()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{create_dir_all, remove_dir_all, File},
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::parse::{TargetGraph, TargetName};
+
+    use super::find_up_to_date_targets;
+
+    #[test]
+    fn test_pattern_built_prerequisite() {
+        let directory =
+            std::env::temp_dir().join(format!("mak-test-up-to-date-{}", std::process::id()));
+        create_dir_all(&directory).unwrap();
+        let path = |file_name: &str| directory.join(file_name).to_string_lossy().into_owned();
+        // `foo.c` was edited after `foo.o` and `app` were built.
+        let now = SystemTime::now();
+        for (file_name, age) in [("app", 20), ("foo.o", 30), ("foo.c", 10), ("bar.c", 40)] {
+            File::create(path(file_name))
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap();
+        }
+
+        let database = format!(
+            "# Implicit Rules\n\n%.o: %.c\n\n# Files\n\n{app}: {foo_o}\n\n# Not a target:\n{foo_o}:\n#  Implicit rule search has not been done.\n\n{bar}: {bar_c}\n\n# Not a target:\n{bar_c}:\n#  Implicit rule search has been done.\n",
+            app = path("app"),
+            foo_o = path("foo.o"),
+            bar = path("bar"),
+            bar_c = path("bar.c"),
+        );
+        let target_graph = TargetGraph::try_from(&database).unwrap();
+        let up_to_date_targets = find_up_to_date_targets(&target_graph, Path::new(""));
+        remove_dir_all(&directory).unwrap();
+
+        // `make` hasn't looked for a rule for `foo.o`, and `%.o: %.c` can remake it.
+        assert!(!up_to_date_targets.contains(&TargetName(path("foo.o"))));
+        assert!(!up_to_date_targets.contains(&TargetName(path("app"))));
+        // `make` found no rule for `bar.c`, so it is a plain source file.
+        assert!(up_to_date_targets.contains(&TargetName(path("bar.c"))));
+    }
+}
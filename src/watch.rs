@@ -0,0 +1,188 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{CString, OsStr},
+    io,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use libc::c_int;
+
+use crate::{
+    cancel::Cancellation,
+    parse::{TargetGraph, TargetName},
+};
+
+/// How long the filesystem has to be quiet before a rebuild starts, so that
+/// e.g. saving several files at once only triggers a single rebuild.
+const DEBOUNCE_DURATION: Duration = Duration::from_millis(200);
+/// How often to check whether `mak` received a signal while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const WATCH_MASK: u32 =
+    libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE | libc::IN_DELETE | libc::IN_ATTRIB;
+
+impl TargetGraph {
+    /// Prerequisites of `target_names` that are source files rather than
+    /// built by `make`, i.e. that exist and have no recipe (after pattern
+    /// rules have been resolved). Paths are relative to `make_directory`.
+    pub(crate) fn source_files(
+        &self,
+        target_names: &[TargetName],
+        make_directory: &Path,
+    ) -> Vec<TargetName> {
+        self.build_order(target_names)
+            .into_iter()
+            .filter(|target_name| {
+                self.metadata
+                    .get(target_name)
+                    .is_some_and(|metadata| !metadata.phony && metadata.recipe.is_empty())
+                    && make_directory.join(&target_name.0).exists()
+            })
+            .collect()
+    }
+}
+
+/// Watches files for changes using `inotify`.
+///
+/// The directories containing the files are watched (rather than the files
+/// themselves), since many editors save by replacing the file.
+pub(crate) struct FileWatcher {
+    fd: OwnedFd,
+    /// The (possibly empty) directory path for each watch descriptor.
+    directories: HashMap<c_int, PathBuf>,
+    /// The target name of each watched file, by its path.
+    files: HashMap<PathBuf, TargetName>,
+}
+
+impl FileWatcher {
+    /// `target_names` are relative to `make_directory`.
+    pub(crate) fn new(target_names: &[TargetName], make_directory: &Path) -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut file_watcher = Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            directories: HashMap::new(),
+            files: HashMap::new(),
+        };
+        let mut watched_directories = HashSet::<PathBuf>::new();
+        for target_name in target_names {
+            let path = make_directory.join(&target_name.0);
+            let directory = path.parent().unwrap_or(Path::new("")).to_owned();
+            file_watcher.files.insert(path, target_name.clone());
+            if !watched_directories.insert(directory.clone()) {
+                continue;
+            }
+            let directory_for_watch = if directory.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                &directory
+            };
+            let directory_cstring = CString::new(directory_for_watch.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let wd = unsafe {
+                libc::inotify_add_watch(
+                    file_watcher.fd.as_raw_fd(),
+                    directory_cstring.as_ptr(),
+                    WATCH_MASK,
+                )
+            };
+            if wd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            file_watcher.directories.insert(wd, directory);
+        }
+        Ok(file_watcher)
+    }
+
+    pub(crate) fn num_files(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Blocks until at least one watched file has changed and the filesystem
+    /// has been quiet for a moment, and returns the changed files. Returns
+    /// `None` if `mak` receives a signal in the meantime.
+    pub(crate) fn wait_for_changes(&self, cancellation: &Cancellation) -> Option<Vec<TargetName>> {
+        let mut changed_files = HashSet::<TargetName>::new();
+        let mut last_change_time: Option<Instant> = None;
+        loop {
+            if cancellation.signal().is_some() {
+                return None;
+            }
+            if let Some(last_change_time) = last_change_time {
+                if last_change_time.elapsed() >= DEBOUNCE_DURATION {
+                    return Some(changed_files.into_iter().collect());
+                }
+            }
+            if !self.poll(POLL_INTERVAL) {
+                continue;
+            }
+            let new_changed_files = self.read_events();
+            if !new_changed_files.is_empty() {
+                changed_files.extend(new_changed_files);
+                last_change_time = Some(Instant::now());
+            }
+        }
+    }
+
+    /// Returns whether there are events to read.
+    fn poll(&self, timeout: Duration) -> bool {
+        let mut poll_fd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let result = unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as c_int) };
+        result > 0 && (poll_fd.revents & libc::POLLIN) != 0
+    }
+
+    /// Reads all pending events, and returns the watched files that they refer to.
+    fn read_events(&self) -> Vec<TargetName> {
+        let mut changed_files = vec![];
+        // Large enough for many events, and aligned for `inotify_event`.
+        let mut buffer = [0u64; 1024];
+        loop {
+            let num_bytes = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    std::mem::size_of_val(&buffer),
+                )
+            };
+            if num_bytes <= 0 {
+                // No more events (`EAGAIN`), or an error that reading again won't fix.
+                return changed_files;
+            }
+            let bytes = unsafe {
+                std::slice::from_raw_parts(buffer.as_ptr() as *const u8, num_bytes as usize)
+            };
+            let mut offset = 0;
+            while offset + std::mem::size_of::<libc::inotify_event>() <= bytes.len() {
+                let event = unsafe {
+                    std::ptr::read_unaligned(bytes[offset..].as_ptr() as *const libc::inotify_event)
+                };
+                let name_start = offset + std::mem::size_of::<libc::inotify_event>();
+                let name_end = name_start + event.len as usize;
+                offset = name_end;
+                let Some(directory) = self.directories.get(&event.wd) else {
+                    continue;
+                };
+                // The name is padded with null bytes.
+                let name = bytes[name_start..name_end.min(bytes.len())]
+                    .split(|byte| *byte == 0)
+                    .next()
+                    .unwrap_or_default();
+                let path = directory.join(OsStr::from_bytes(name));
+                if let Some(target_name) = self.files.get(&path) {
+                    changed_files.push(target_name.clone());
+                }
+            }
+        }
+    }
+}